@@ -22,10 +22,14 @@
 //! devolve to a single, universal rumor, which when it is received by the winner will result in
 //! the election finishing. There can, in the end, be only one.
 
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, RwLock};
 
 use habitat_core::service::ServiceGroup;
 use protobuf::{Message, RepeatedField};
+use time::{SteadyTime, Duration as TimeDuration};
 
 use error::Result;
 use message::swim::{Election as ProtoElection, Election_Status, Rumor as ProtoRumor,
@@ -210,9 +214,187 @@ impl Rumor for Election {
     }
 }
 
+/// Who is in a service group, and which of them are alive, as seen from one member.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Census {
+    /// Every member with a service rumor in the group.
+    pub population: Vec<String>,
+    /// The members of the population we believe are alive.
+    pub electorate: Vec<String>,
+}
+
+impl Census {
+    /// Create a new census. Both lists are sorted, so censuses compare by membership alone.
+    pub fn new(mut population: Vec<String>, mut electorate: Vec<String>) -> Census {
+        population.sort();
+        electorate.sort();
+        Census {
+            population: population,
+            electorate: electorate,
+        }
+    }
+}
+
+/// The census an election counts quorum and votes against, as seen from one member.
+///
+/// Mid-partition, members briefly disagree about who is alive. Rather than recomputing quorum from
+/// every flicker in health, a newly observed census only replaces the snapshot once it has been
+/// seen unchanged for the electorate settle window. With a zero window, every observation is
+/// adopted immediately.
+///
+/// This is a debounce on one members view, not an agreed electorate. Members never exchange
+/// snapshots, so while their views differ they can still count quorum and votes against
+/// different electorates.
+#[derive(Debug, Clone)]
+pub struct ElectorateSnapshot {
+    census: Census,
+    candidate: Option<(Census, SteadyTime)>,
+}
+
+impl ElectorateSnapshot {
+    /// Create a new snapshot from a census.
+    pub fn new(census: Census) -> ElectorateSnapshot {
+        ElectorateSnapshot {
+            census: census,
+            candidate: None,
+        }
+    }
+
+    /// The settled census.
+    pub fn census(&self) -> &Census {
+        &self.census
+    }
+
+    /// Returns the settled census if observing `census` now would leave the snapshot unchanged,
+    /// so callers can skip taking a write lock.
+    pub fn peek(&self, census: &Census, settle: TimeDuration) -> Option<Census> {
+        match self.candidate {
+            None if *census == self.census => Some(self.census.clone()),
+            Some((ref candidate, since)) if candidate == census &&
+                                            SteadyTime::now() - since < settle => {
+                Some(self.census.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Record the census as it is currently observed, and return the settled census.
+    pub fn observe(&mut self, census: Census, settle: TimeDuration) -> Census {
+        if census == self.census {
+            self.candidate = None;
+            return self.census.clone();
+        }
+        let now = SteadyTime::now();
+        let since = match self.candidate {
+            Some((ref candidate, since)) if *candidate == census => since,
+            _ => now,
+        };
+        if now - since >= settle {
+            self.census = census;
+            self.candidate = None;
+        } else {
+            self.candidate = Some((census, since));
+        }
+        self.census.clone()
+    }
+}
+
+/// A callback run with the new leader of a service group, or `None` when it is leaderless.
+pub type LeaderChangeCallback = Box<Fn(Option<String>) + Send + Sync>;
+
+/// Tracks the winner of the finished election for each service group, and runs the registered
+/// callbacks whenever it changes.
+///
+/// Changes are queued in the order they are recorded, and delivered one at a time by whichever
+/// thread finds the queue idle. Callbacks run from a copy of the registered list, with no lock
+/// held, so a callback may itself register callbacks or record a change.
+#[derive(Clone, Default)]
+pub struct LeaderChangeCallbacks {
+    callbacks: Arc<RwLock<HashMap<String, Vec<Arc<LeaderChangeCallback>>>>>,
+    leaders: Arc<Mutex<Leaders>>,
+}
+
+#[derive(Debug, Default)]
+struct Leaders {
+    current: HashMap<String, Option<String>>,
+    pending: VecDeque<(String, Option<String>)>,
+    delivering: bool,
+}
+
+impl fmt::Debug for LeaderChangeCallbacks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "LeaderChangeCallbacks {{ leaders: {:?} }}",
+               self.leaders.lock().expect("Leaders lock is poisoned").current)
+    }
+}
+
+impl LeaderChangeCallbacks {
+    /// Register a callback for the given service group.
+    pub fn insert(&self, key: String, callback: LeaderChangeCallback) {
+        let mut callbacks = self.callbacks.write().expect("Leader callbacks lock is poisoned");
+        callbacks.entry(key).or_insert(Vec::new()).push(Arc::new(callback));
+    }
+
+    /// Record the current leader of the given service group. If it differs from the last leader
+    /// we saw, queues the change for every callback registered for the group and returns true.
+    ///
+    /// If no other thread is delivering changes, this one delivers the whole queue before
+    /// returning; otherwise the thread already delivering picks the change up.
+    pub fn update(&self, key: &str, leader: Option<String>) -> bool {
+        {
+            let mut leaders = self.leaders.lock().expect("Leaders lock is poisoned");
+            let changed = match leaders.current.get(key) {
+                Some(current) => *current != leader,
+                None => leader.is_some(),
+            };
+            if !changed {
+                return false;
+            }
+            leaders.current.insert(String::from(key), leader.clone());
+            leaders.pending.push_back((String::from(key), leader));
+            if leaders.delivering {
+                return true;
+            }
+            leaders.delivering = true;
+        }
+        self.deliver();
+        true
+    }
+
+    fn deliver(&self) {
+        loop {
+            let (key, leader) = {
+                let mut leaders = self.leaders.lock().expect("Leaders lock is poisoned");
+                match leaders.pending.pop_front() {
+                    Some(change) => change,
+                    None => {
+                        leaders.delivering = false;
+                        return;
+                    }
+                }
+            };
+            let group_callbacks = {
+                let callbacks = self.callbacks.read().expect("Leader callbacks lock is poisoned");
+                callbacks.get(&key).map_or(Vec::new(), |c| c.clone())
+            };
+            for callback in group_callbacks.iter() {
+                callback(leader.clone());
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use election::Election;
+    use std::thread;
+    use std::time::Duration;
+
+    use time::Duration as TimeDuration;
+
+    use std::sync::{Arc, Mutex};
+
+    use election::{Census, Election, ElectorateSnapshot, LeaderChangeCallbacks};
     use rumor::Rumor;
     use habitat_core::service::ServiceGroup;
 
@@ -254,4 +436,65 @@ mod tests {
         assert_eq!(e1.get_member_id(), "d");
         assert_eq!(e1.get_votes().len(), 4);
     }
+
+    fn census(electorate: &[&str]) -> Census {
+        Census::new(vec![String::from("a"), String::from("b")],
+                    electorate.iter().map(|id| String::from(*id)).collect())
+    }
+
+    #[test]
+    fn electorate_snapshot_adopts_immediately_without_a_settle_window() {
+        let mut es = ElectorateSnapshot::new(census(&["a", "b"]));
+        let settled = es.observe(census(&["a"]), TimeDuration::zero());
+        assert_eq!(settled, census(&["a"]));
+    }
+
+    #[test]
+    fn electorate_snapshot_holds_during_the_settle_window() {
+        let mut es = ElectorateSnapshot::new(census(&["b", "a"]));
+        let settled = es.observe(census(&["a"]), TimeDuration::seconds(30));
+        assert_eq!(settled, census(&["a", "b"]));
+        assert_eq!(es.peek(&census(&["a"]), TimeDuration::seconds(30)),
+                   Some(census(&["a", "b"])));
+        let settled = es.observe(census(&["a", "b"]), TimeDuration::seconds(30));
+        assert_eq!(settled, census(&["a", "b"]));
+    }
+
+    #[test]
+    fn electorate_snapshot_adopts_after_the_settle_window() {
+        let mut es = ElectorateSnapshot::new(census(&["a", "b"]));
+        es.observe(census(&["a"]), TimeDuration::milliseconds(10));
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(es.peek(&census(&["a"]), TimeDuration::milliseconds(10)), None);
+        let settled = es.observe(census(&["a"]), TimeDuration::milliseconds(10));
+        assert_eq!(settled, census(&["a"]));
+    }
+
+    #[test]
+    fn electorate_snapshot_settles_the_population_with_the_electorate() {
+        let mut es = ElectorateSnapshot::new(census(&["a", "b"]));
+        let grown = Census::new(vec![String::from("a"), String::from("b"), String::from("c")],
+                                vec![String::from("a"), String::from("b")]);
+        let settled = es.observe(grown, TimeDuration::seconds(30));
+        assert_eq!(settled.population.len(), 2);
+    }
+
+    #[test]
+    fn leader_change_callbacks_run_in_the_order_changes_were_recorded() {
+        let lcc = LeaderChangeCallbacks::default();
+        let seen: Arc<Mutex<Vec<Option<String>>>> = Arc::new(Mutex::new(Vec::new()));
+        let callback_lcc = lcc.clone();
+        let callback_seen = seen.clone();
+        lcc.insert(String::from("witcher.prod"),
+                   Box::new(move |leader| {
+                       callback_seen.lock().unwrap().push(leader.clone());
+                       if leader == Some(String::from("a")) {
+                           callback_lcc.update("witcher.prod", Some(String::from("b")));
+                       }
+                   }));
+        assert!(lcc.update("witcher.prod", Some(String::from("a"))));
+        assert!(!lcc.update("witcher.prod", Some(String::from("b"))));
+        assert_eq!(*seen.lock().unwrap(),
+                   vec![Some(String::from("a")), Some(String::from("b"))]);
+    }
 }
@@ -33,7 +33,7 @@ use message::swim::{Member as ProtoMember, Membership as ProtoMembership,
 const PINGREQ_TARGETS: usize = 5;
 
 /// The health of a node.
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Health {
     Alive,
     Suspect,
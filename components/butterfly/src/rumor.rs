@@ -24,11 +24,15 @@
 //! new rumors, and dispatch them according to thier `kind`.
 
 use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
 use std::default::Default;
+use std::hash::{Hash, Hasher};
 use std::sync::{Arc, RwLock};
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-use message::swim::Rumor_Type;
+use protobuf::{Message, RepeatedField};
+
+use message::swim::{Rumor as ProtoRumor, Rumor_Type};
 use error::{Result, Error};
 
 /// The description of a `RumorKey`.
@@ -58,6 +62,160 @@ impl RumorKey {
     }
 }
 
+/// Returns a digest of the contents of a rumor, ignoring who forwarded it to us. Two rumors with
+/// the same key and digest carry the same information.
+pub fn rumor_digest(rumor: &ProtoRumor) -> Option<u64> {
+    let bytes = match rumor.get_field_type() {
+        Rumor_Type::Member => rumor.get_member().write_to_bytes(),
+        Rumor_Type::Service => rumor.get_service().write_to_bytes(),
+        Rumor_Type::Election => rumor.get_election().write_to_bytes(),
+        Rumor_Type::Fake | Rumor_Type::Fake2 => return None,
+    };
+    match bytes {
+        Ok(bytes) => {
+            let mut hasher = DefaultHasher::new();
+            bytes.hash(&mut hasher);
+            Some(hasher.finish())
+        }
+        Err(e) => {
+            error!("Cannot digest rumor {:?}: {}", rumor, e);
+            None
+        }
+    }
+}
+
+/// Returns a fingerprint of one version of a rumor, from its key and the digest of its contents.
+pub fn rumor_fingerprint(rumor_key: &RumorKey, digest: u64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    rumor_key.hash(&mut hasher);
+    digest.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Returns a short hash of a rumor key, so a digest can name a rumor without spelling out its key.
+pub fn rumor_key_hash(rumor_key: &RumorKey) -> u32 {
+    let mut hasher = DefaultHasher::new();
+    rumor_key.hash(&mut hasher);
+    hasher.finish() as u32
+}
+
+/// The prefix of the rumor tag that carries a piggybacked digest.
+const DIGEST_TAG: &'static str = "digest:";
+
+/// A digest one member piggybacks on its gossip: for each rumor it is pushing to the recipient,
+/// the hash of the rumor key and the fingerprint of the version it holds.
+///
+/// The epoch changes every time the member starts, so a member that restarts without its rumors
+/// doesn't leave its old digest behind. Forwarded rumors keep the `from_id` of the member that
+/// created them, so the digest names its member itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PiggybackedDigest {
+    pub member_id: String,
+    pub epoch: String,
+    pub entries: Vec<(u32, u64)>,
+}
+
+/// Piggyback a digest onto an outgoing rumor, as a single tag. Any other tags are left alone.
+pub fn set_digest_tag(rumor: &mut ProtoRumor, digest: &PiggybackedDigest) {
+    take_digest_tag(rumor);
+    let entries: Vec<String> = digest.entries
+        .iter()
+        .map(|&(key_hash, fingerprint)| format!("{:08x}{:016x}", key_hash, fingerprint))
+        .collect();
+    rumor.mut_tag()
+        .push(format!("{}{}:{}:{}",
+                      DIGEST_TAG,
+                      digest.epoch,
+                      entries.join(","),
+                      digest.member_id));
+}
+
+/// Strip a piggybacked digest from an incoming rumor, so it is never stored or forwarded, and
+/// return it. Only the digest tag is removed; a malformed one is dropped and ignored.
+pub fn take_digest_tag(rumor: &mut ProtoRumor) -> Option<PiggybackedDigest> {
+    if !rumor.get_tag().iter().any(|t| t.starts_with(DIGEST_TAG)) {
+        return None;
+    }
+    let (digest_tags, tags): (Vec<String>, Vec<String>) =
+        rumor.take_tag().into_vec().into_iter().partition(|t| t.starts_with(DIGEST_TAG));
+    rumor.set_tag(RepeatedField::from_vec(tags));
+    digest_tags.iter().filter_map(|t| parse_digest_tag(&t[DIGEST_TAG.len()..])).next()
+}
+
+fn parse_digest_tag(tag: &str) -> Option<PiggybackedDigest> {
+    let mut parts = tag.splitn(3, ':');
+    let epoch = match parts.next() {
+        Some(epoch) => String::from(epoch),
+        None => return None,
+    };
+    let entries = match parts.next() {
+        Some(entries) => entries,
+        None => return None,
+    };
+    let member_id = match parts.next() {
+        Some(member_id) if member_id.len() > 0 => String::from(member_id),
+        _ => return None,
+    };
+    let mut parsed = Vec::new();
+    for entry in entries.split(',').filter(|e| e.len() > 0) {
+        if entry.len() != 24 || !entry.chars().all(|c| c.is_digit(16)) {
+            return None;
+        }
+        match (u32::from_str_radix(&entry[..8], 16), u64::from_str_radix(&entry[8..], 16)) {
+            (Ok(key_hash), Ok(fingerprint)) => parsed.push((key_hash, fingerprint)),
+            _ => return None,
+        }
+    }
+    Some(PiggybackedDigest {
+        member_id: member_id,
+        epoch: epoch,
+        entries: parsed,
+    })
+}
+
+/// The rumor versions each member has told us it holds, from the digests it piggybacks on its
+/// gossip. A new digest updates the rumors it names and leaves the rest alone; a digest from a
+/// new epoch starts over. When gossip suppression is on, we skip sending a member rumors it holds.
+#[derive(Debug, Clone)]
+pub struct PeerRumorDigests {
+    digests: Arc<RwLock<HashMap<String, (String, HashMap<u32, u64>)>>>,
+}
+
+impl Default for PeerRumorDigests {
+    fn default() -> PeerRumorDigests {
+        PeerRumorDigests { digests: Arc::new(RwLock::new(HashMap::new())) }
+    }
+}
+
+impl PeerRumorDigests {
+    /// Apply a digest a member piggybacked on its gossip.
+    pub fn update(&self, digest: PiggybackedDigest) {
+        let mut digests = self.digests.write().expect("Peer rumor digests lock poisoned");
+        let entry = digests.entry(digest.member_id)
+            .or_insert((digest.epoch.clone(), HashMap::new()));
+        if entry.0 != digest.epoch {
+            *entry = (digest.epoch, HashMap::new());
+        }
+        for (key_hash, fingerprint) in digest.entries.into_iter() {
+            entry.1.insert(key_hash, fingerprint);
+        }
+    }
+
+    /// Forget everything the given member has told us it holds.
+    pub fn remove(&self, member_id: &str) {
+        let mut digests = self.digests.write().expect("Peer rumor digests lock poisoned");
+        digests.remove(member_id);
+    }
+
+    /// Returns true if the given member has told us it holds this exact version of a rumor.
+    pub fn contains(&self, member_id: &str, key_hash: u32, fingerprint: u64) -> bool {
+        let digests = self.digests.read().expect("Peer rumor digests lock poisoned");
+        digests.get(member_id)
+            .and_then(|&(_, ref entries)| entries.get(&key_hash))
+            .map_or(false, |f| *f == fingerprint)
+    }
+}
+
 /// A representation of a Rumor; implemented by all the concrete types we share as rumors. The
 /// exception is the Membership rumor, since it's not actually a rumor in the same vein.
 pub trait Rumor {
@@ -555,4 +713,80 @@ mod tests {
         }
 
     }
+
+    mod peer_rumor_digests {
+        use super::FakeRumor;
+        use message::swim::{Rumor as ProtoRumor, Rumor_Type};
+        use rumor::{self, PeerRumorDigests, PiggybackedDigest, RumorKey};
+
+        fn digest(epoch: &str, entries: Vec<(u32, u64)>) -> PiggybackedDigest {
+            PiggybackedDigest {
+                member_id: String::from("fake"),
+                epoch: String::from(epoch),
+                entries: entries,
+            }
+        }
+
+        #[test]
+        fn contains_a_rumor_version_the_member_told_us_about() {
+            let pd = PeerRumorDigests::default();
+            pd.update(digest("1", vec![(1, 42)]));
+            assert!(pd.contains("fake", 1, 42));
+            assert_eq!(pd.contains("fake", 1, 43), false);
+            assert_eq!(pd.contains("trump", 1, 42), false);
+        }
+
+        #[test]
+        fn a_new_digest_updates_only_the_rumors_it_names() {
+            let pd = PeerRumorDigests::default();
+            pd.update(digest("1", vec![(1, 42), (2, 42)]));
+            pd.update(digest("1", vec![(1, 43)]));
+            assert_eq!(pd.contains("fake", 1, 42), false);
+            assert!(pd.contains("fake", 1, 43));
+            assert!(pd.contains("fake", 2, 42));
+            pd.remove("fake");
+            assert_eq!(pd.contains("fake", 1, 43), false);
+        }
+
+        #[test]
+        fn a_digest_from_a_new_epoch_starts_over() {
+            let pd = PeerRumorDigests::default();
+            pd.update(digest("1", vec![(1, 42)]));
+            pd.update(digest("2", vec![(2, 42)]));
+            assert_eq!(pd.contains("fake", 1, 42), false);
+            assert!(pd.contains("fake", 2, 42));
+        }
+
+        #[test]
+        fn fingerprints_differ_by_key_and_digest() {
+            let rk = RumorKey::from(&FakeRumor::default());
+            let other = RumorKey::new(Rumor_Type::Fake, "other", "");
+            assert_eq!(rumor::rumor_fingerprint(&rk, 42),
+                       rumor::rumor_fingerprint(&rk, 42));
+            assert!(rumor::rumor_fingerprint(&rk, 42) != rumor::rumor_fingerprint(&rk, 43));
+            assert!(rumor::rumor_fingerprint(&rk, 42) != rumor::rumor_fingerprint(&other, 42));
+        }
+
+        #[test]
+        fn digest_tag_round_trips_and_leaves_other_tags_alone() {
+            let mut proto = ProtoRumor::new();
+            proto.set_field_type(Rumor_Type::Fake);
+            proto.mut_tag().push(String::from("witcher"));
+            let sent = digest("1", vec![(1, 42), (0xffffffff, 43)]);
+            rumor::set_digest_tag(&mut proto, &sent);
+            assert_eq!(proto.get_tag().len(), 2);
+            assert_eq!(rumor::take_digest_tag(&mut proto), Some(sent));
+            assert_eq!(proto.get_tag(), &[String::from("witcher")][..]);
+            assert_eq!(rumor::take_digest_tag(&mut proto), None);
+        }
+
+        #[test]
+        fn a_malformed_digest_tag_is_dropped() {
+            let mut proto = ProtoRumor::new();
+            proto.set_field_type(Rumor_Type::Fake);
+            proto.mut_tag().push(String::from("digest:1:nonsense:fake"));
+            assert_eq!(rumor::take_digest_tag(&mut proto), None);
+            assert_eq!(proto.get_tag().len(), 0);
+        }
+    }
 }
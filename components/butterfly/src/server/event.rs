@@ -0,0 +1,88 @@
+// Copyright (c) 2016 Chef Software Inc. and/or applicable contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Service group events.
+//!
+//! Subscribers register for a single service group, and receive only the events that concern
+//! it: members joining or leaving the group, and changes of leader.
+
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
+
+/// Something that happened to a service group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupEvent {
+    /// A member started running the service, or came back to life while running it.
+    MemberJoined(String),
+    /// A member running the service was confirmed dead.
+    MemberLeft(String),
+    /// The winner of the groups finished election changed; `None` when it is leaderless.
+    LeaderChanged(Option<String>),
+}
+
+/// The subscribers for each service group.
+#[derive(Debug, Clone)]
+pub struct GroupSubscribers {
+    subscribers: Arc<Mutex<HashMap<String, Vec<mpsc::Sender<GroupEvent>>>>>,
+}
+
+impl Default for GroupSubscribers {
+    fn default() -> GroupSubscribers {
+        GroupSubscribers { subscribers: Arc::new(Mutex::new(HashMap::new())) }
+    }
+}
+
+impl GroupSubscribers {
+    /// Subscribe to the events for a service group.
+    pub fn subscribe(&self, key: String) -> mpsc::Receiver<GroupEvent> {
+        let (tx, rx) = mpsc::channel();
+        let mut subscribers = self.subscribers.lock().expect("Group subscribers lock poisoned");
+        subscribers.entry(key).or_insert(Vec::new()).push(tx);
+        rx
+    }
+
+    /// Send an event to every subscriber of a service group. Subscribers who have hung up are
+    /// dropped.
+    pub fn send(&self, key: &str, event: GroupEvent) {
+        let mut subscribers = self.subscribers.lock().expect("Group subscribers lock poisoned");
+        if let Some(senders) = subscribers.get_mut(key) {
+            senders.retain(|tx| tx.send(event.clone()).is_ok());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use server::event::{GroupEvent, GroupSubscribers};
+
+    #[test]
+    fn send_only_reaches_subscribers_of_the_group() {
+        let gs = GroupSubscribers::default();
+        let witcher = gs.subscribe(String::from("witcher.prod"));
+        let yennefer = gs.subscribe(String::from("yennefer.prod"));
+        gs.send("witcher.prod", GroupEvent::MemberJoined(String::from("geralt")));
+        assert_eq!(witcher.try_recv(),
+                   Ok(GroupEvent::MemberJoined(String::from("geralt"))));
+        assert!(yennefer.try_recv().is_err());
+    }
+
+    #[test]
+    fn send_drops_subscribers_who_hung_up() {
+        let gs = GroupSubscribers::default();
+        let witcher = gs.subscribe(String::from("witcher.prod"));
+        drop(witcher);
+        gs.send("witcher.prod", GroupEvent::LeaderChanged(None));
+        assert_eq!(gs.subscribers.lock().unwrap().get("witcher.prod").unwrap().len(), 0);
+    }
+}
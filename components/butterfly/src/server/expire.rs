@@ -51,7 +51,10 @@ impl<'a> Expire<'a> {
                 let now = SteadyTime::now();
                 if *suspect + self.timing.suspicion_timeout_duration() > now {
                     expired_list.push(String::from(id));
-                    self.server.member_list.insert_health_by_id(id, Health::Confirmed);
+                    let previous_health = self.server.member_list.health_of_by_id(id);
+                    if self.server.member_list.insert_health_by_id(id, Health::Confirmed) {
+                        self.server.notify_health_change(id, previous_health, Health::Confirmed);
+                    }
                     self.server.member_list.with_member(id, |has_member| {
                         let member = has_member.expect("Member does not exist when expiring it");
                         debug!("Marking {:?} as Confirmed", member);
@@ -59,12 +59,18 @@ impl<'a> Inbound<'a> {
             }
             match self.socket.recv_from(&mut recv_buffer[..]) {
                 Ok((length, addr)) => {
+                    if self.server.inject_decode_failure() {
+                        error!("Error parsing protobuf: injected failure");
+                        self.server.dead_letter(&recv_buffer[0..length]);
+                        continue;
+                    }
                     let msg: Swim = match protobuf::parse_from_bytes(&recv_buffer[0..length]) {
                         Ok(msg) => msg,
                         Err(e) => {
                             // NOTE: In the future, we might want to blacklist people who send us
                             // garbage all the time.
                             error!("Error parsing protobuf: {:?}", e);
+                            self.server.dead_letter(&recv_buffer[0..length]);
                             continue;
                         }
                     };
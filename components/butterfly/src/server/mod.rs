@@ -18,6 +18,7 @@
 //! 3 separate threads - inbound (incoming connections), outbound (the Probe protocl), and expire
 //! (turning Suspect members into Confirmed members).
 
+pub mod event;
 pub mod expire;
 pub mod inbound;
 pub mod outbound;
@@ -25,25 +26,35 @@ pub mod pull;
 pub mod push;
 pub mod timing;
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::hash_map::DefaultHasher;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::net::{ToSocketAddrs, UdpSocket, SocketAddr};
 use std::str::FromStr;
 use std::sync::{Arc, RwLock};
-use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
-use std::sync::mpsc::channel;
+use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, channel};
 use std::time::Duration;
 use std::thread;
 
 use habitat_core::service::ServiceGroup;
+use protobuf::{CodedInputStream, Message};
+use time::Duration as TimeDuration;
+use uuid::Uuid;
 
 use error::{Result, Error};
 use member::{Member, Health, MemberList};
 use trace::{Trace, TraceKind};
-use rumor::{Rumor, RumorStore, RumorList, RumorKey};
+use rumor::{self, Rumor, RumorStore, RumorList, RumorKey, RumorVec, PeerRumorDigests,
+            PiggybackedDigest};
 use service::Service;
-use election::Election;
-use message::swim::Election_Status;
+use server::event::{GroupEvent, GroupSubscribers};
+use election::{Census, Election, ElectorateSnapshot, LeaderChangeCallback, LeaderChangeCallbacks};
+use message::swim::{Election_Status, Rumor as ProtoRumor, Rumor_Type};
+
+/// How many undecodable messages we keep around for inspection.
+const DEAD_LETTER_MAX: usize = 64;
 
 /// The server struct. Is thread-safe.
 #[derive(Debug, Clone)]
@@ -57,12 +68,27 @@ pub struct Server {
     pub election_store: RumorStore<Election>,
     pub swim_addr: Arc<RwLock<SocketAddr>>,
     pub gossip_addr: Arc<RwLock<SocketAddr>>,
+    pub electorates: Arc<RwLock<HashMap<String, ElectorateSnapshot>>>,
+    pub electorate_settle_window_ms: Arc<AtomicUsize>,
+    pub leader_change_callbacks: LeaderChangeCallbacks,
+    pub group_subscribers: GroupSubscribers,
+    pub gossip_suppression: Arc<AtomicBool>,
+    pub peer_rumor_digests: PeerRumorDigests,
+    pub digest_epoch: Arc<String>,
     // These are all here for testing support
+    pub rumor_send_counts: Arc<RwLock<HashMap<(String, RumorKey), usize>>>,
     pub pause: Arc<AtomicBool>,
     pub trace: Arc<RwLock<Trace>>,
     pub swim_rounds: Arc<AtomicIsize>,
     pub gossip_rounds: Arc<AtomicIsize>,
+    probes_in_flight: Arc<AtomicUsize>,
+    probes_in_flight_high_water: Arc<AtomicUsize>,
     pub blacklist: Arc<RwLock<HashSet<String>>>,
+    pub gossip_paused_for: Arc<RwLock<HashSet<String>>>,
+    pub malformed_messages: Arc<AtomicUsize>,
+    pub unencodable_rumors: Arc<AtomicUsize>,
+    pub dead_letters: Arc<RwLock<VecDeque<Vec<u8>>>>,
+    pub failure_injection: FailureInjection,
 }
 
 impl Server {
@@ -92,11 +118,26 @@ impl Server {
             election_store: RumorStore::default(),
             swim_addr: Arc::new(RwLock::new(swim_socket_addr)),
             gossip_addr: Arc::new(RwLock::new(gossip_socket_addr)),
+            electorates: Arc::new(RwLock::new(HashMap::new())),
+            electorate_settle_window_ms: Arc::new(AtomicUsize::new(0)),
+            leader_change_callbacks: LeaderChangeCallbacks::default(),
+            group_subscribers: GroupSubscribers::default(),
+            gossip_suppression: Arc::new(AtomicBool::new(false)),
+            peer_rumor_digests: PeerRumorDigests::default(),
+            digest_epoch: Arc::new(Uuid::new_v4().simple().to_string()),
+            rumor_send_counts: Arc::new(RwLock::new(HashMap::new())),
             pause: Arc::new(AtomicBool::new(false)),
             trace: Arc::new(RwLock::new(trace)),
             swim_rounds: Arc::new(AtomicIsize::new(0)),
             gossip_rounds: Arc::new(AtomicIsize::new(0)),
+            probes_in_flight: Arc::new(AtomicUsize::new(0)),
+            probes_in_flight_high_water: Arc::new(AtomicUsize::new(0)),
             blacklist: Arc::new(RwLock::new(HashSet::new())),
+            gossip_paused_for: Arc::new(RwLock::new(HashSet::new())),
+            malformed_messages: Arc::new(AtomicUsize::new(0)),
+            unencodable_rumors: Arc::new(AtomicUsize::new(0)),
+            dead_letters: Arc::new(RwLock::new(VecDeque::new())),
+            failure_injection: FailureInjection::default(),
         })
     }
 
@@ -148,6 +189,40 @@ impl Server {
         }
     }
 
+    /// The number of outbound probes currently waiting on an Ack.
+    pub fn probes_in_flight(&self) -> usize {
+        self.probes_in_flight.load(Ordering::SeqCst)
+    }
+
+    /// The largest number of outbound probes that have been outstanding at the same time.
+    ///
+    /// This is useful in integration testing, to check that the outbound thread honors
+    /// `Timing::max_probes_in_flight`.
+    pub fn probes_in_flight_high_water(&self) -> usize {
+        self.probes_in_flight_high_water.load(Ordering::SeqCst)
+    }
+
+    /// Records that an outbound probe has been sent, updating the high water mark if needed. Only
+    /// the outbound thread should call this.
+    fn probe_started(&self) {
+        let in_flight = self.probes_in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut high_water = self.probes_in_flight_high_water.load(Ordering::SeqCst);
+        while in_flight > high_water {
+            let previous = self.probes_in_flight_high_water
+                .compare_and_swap(high_water, in_flight, Ordering::SeqCst);
+            if previous == high_water {
+                break;
+            }
+            high_water = previous;
+        }
+    }
+
+    /// Records that an outbound probe has completed, either with an Ack or with suspicion. Only
+    /// the outbound thread should call this.
+    fn probe_finished(&self) {
+        self.probes_in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+
     /// Start the server, aloung with a `Timing` for outbound connections. Spawns the `inbound`,
     /// `outbound`, and `expire` threads.
     ///
@@ -213,6 +288,96 @@ impl Server {
         Ok(())
     }
 
+    /// Whether we skip pushing rumors to members whose latest digest says they already have them.
+    pub fn gossip_suppression(&self) -> bool {
+        self.gossip_suppression.load(Ordering::Relaxed)
+    }
+
+    /// Turn gossip suppression on or off. Off by default.
+    ///
+    /// With suppression on, the first rumor we push to a member each round carries a digest of the
+    /// versions we hold of every rumor in that members rumor list. We remember the latest version
+    /// each member has told us it holds, and won't push a member a rumor it already holds, no
+    /// matter who it heard it from.
+    ///
+    /// A digest only goes out with a rumor, so when everything we would send a member is
+    /// suppressed, it keeps our last digest. That is safe: a digest only names versions we held,
+    /// and we only ever replace a rumor with a newer one, so the member skips sending us versions
+    /// we have or have superseded. If we restart without our rumors, our digest epoch changes and
+    /// the first digest we send replaces the old one.
+    pub fn set_gossip_suppression(&self, suppress: bool) {
+        self.gossip_suppression.store(suppress, Ordering::Relaxed);
+    }
+
+    /// The fingerprint of our current version of a rumor, if we hold it.
+    pub fn rumor_fingerprint(&self, rumor_key: &RumorKey) -> Option<u64> {
+        let mut digest = None;
+        match rumor_key.kind {
+            Rumor_Type::Member => {
+                if self.member_list.contains_member(&rumor_key.key()) {
+                    let mut proto = ProtoRumor::new();
+                    proto.set_field_type(Rumor_Type::Member);
+                    proto.set_member(self.member_list.membership_for(&rumor_key.key()));
+                    digest = rumor::rumor_digest(&proto);
+                }
+            }
+            Rumor_Type::Service => {
+                self.service_store.with_rumor(&rumor_key.key, &rumor_key.id, |s| {
+                    digest = s.and_then(|s| rumor::rumor_digest(&s.proto));
+                });
+            }
+            Rumor_Type::Election => {
+                self.election_store.with_rumor(&rumor_key.key, &rumor_key.id, |e| {
+                    digest = e.and_then(|e| rumor::rumor_digest(&e.proto));
+                });
+            }
+            Rumor_Type::Fake | Rumor_Type::Fake2 => {}
+        }
+        digest.map(|d| rumor::rumor_fingerprint(rumor_key, d))
+    }
+
+    /// The digest we piggyback on gossip to a member: the versions we hold of the rumors in its
+    /// rumor list.
+    pub fn piggyback_digest(&self, rumors: &RumorVec) -> PiggybackedDigest {
+        let mut entries = Vec::new();
+        for &(ref rumor_key, _) in rumors.iter() {
+            if let Some(fingerprint) = self.rumor_fingerprint(rumor_key) {
+                entries.push((rumor::rumor_key_hash(rumor_key), fingerprint));
+            }
+        }
+        PiggybackedDigest {
+            member_id: String::from(self.member_id()),
+            epoch: String::from(&self.digest_epoch[..]),
+            entries: entries,
+        }
+    }
+
+    /// Returns true if the given member has told us it holds our current version of a rumor.
+    pub fn peer_has_rumor(&self, member_id: &str, rumor_key: &RumorKey) -> bool {
+        self.rumor_fingerprint(rumor_key).map_or(false, |f| {
+            self.peer_rumor_digests.contains(member_id, rumor::rumor_key_hash(rumor_key), f)
+        })
+    }
+
+    /// Count a rumor as pushed to a member. Sends are only counted with gossip suppression on, or
+    /// with the `functional` feature.
+    pub fn record_rumor_sent(&self, member_id: &str, rumor_key: &RumorKey) {
+        if !(self.gossip_suppression() || cfg!(feature = "functional")) {
+            return;
+        }
+        let mut counts = self.rumor_send_counts.write().expect("Rumor send counts lock poisoned");
+        *counts.entry((String::from(member_id), rumor_key.clone())).or_insert(0) += 1;
+    }
+
+    /// The number of times we have pushed a rumor to the given member, while sends were being
+    /// counted; see `record_rumor_sent`.
+    ///
+    /// This is useful in integration testing, to check which rumors actually went over the wire.
+    pub fn rumor_send_count(&self, member_id: &str, rumor_key: &RumorKey) -> usize {
+        let counts = self.rumor_send_counts.read().expect("Rumor send counts lock poisoned");
+        counts.get(&(String::from(member_id), rumor_key.clone())).map_or(0, |c| *c)
+    }
+
     /// Blacklist a given address, causing no traffic to be seen.
     pub fn add_to_blacklist(&self, member_id: String) {
         let mut blacklist = self.blacklist.write().expect("Write lock for blacklist is poisoned");
@@ -231,6 +396,89 @@ impl Server {
         blacklist.contains(member_id)
     }
 
+    /// Stop pushing rumors to a given member. Unlike the blacklist, SWIM traffic is unaffected, so
+    /// the member stays Alive; it catches up on the rumors it missed once gossip resumes.
+    pub fn set_gossip_paused_for(&self, member_id: String) {
+        let mut paused = self.gossip_paused_for
+            .write()
+            .expect("Write lock for gossip paused members is poisoned");
+        paused.insert(member_id);
+    }
+
+    /// Resume pushing rumors to a given member.
+    pub fn unset_gossip_paused_for(&self, member_id: &str) {
+        let mut paused = self.gossip_paused_for
+            .write()
+            .expect("Write lock for gossip paused members is poisoned");
+        paused.remove(member_id);
+    }
+
+    /// Check whether gossip to a given member is paused.
+    pub fn is_gossip_paused_for(&self, member_id: &str) -> bool {
+        let paused = self.gossip_paused_for
+            .read()
+            .expect("Read lock for gossip paused members is poisoned");
+        paused.contains(member_id)
+    }
+
+    /// Record a message we could not decode. It counts as malformed, and its raw bytes are kept
+    /// in the dead letters, which hold the most recent `DEAD_LETTER_MAX` messages.
+    pub fn dead_letter(&self, bytes: &[u8]) {
+        self.malformed_messages.fetch_add(1, Ordering::SeqCst);
+        let mut dead_letters = self.dead_letters.write().expect("Dead letters lock is poisoned");
+        if dead_letters.len() >= DEAD_LETTER_MAX {
+            dead_letters.pop_front();
+        }
+        dead_letters.push_back(Vec::from(bytes));
+    }
+
+    /// Record a rumor we could not encode, and so never sent.
+    pub fn unencodable_rumor(&self) {
+        self.unencodable_rumors.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// The number of rumors we could not encode, and so never sent.
+    pub fn unencodable_rumors(&self) -> usize {
+        self.unencodable_rumors.load(Ordering::SeqCst)
+    }
+
+    /// The number of messages we have received and could not decode.
+    pub fn malformed_messages(&self) -> usize {
+        self.malformed_messages.load(Ordering::SeqCst)
+    }
+
+    /// Returns a copy of the dead letters, oldest first.
+    pub fn dead_letters(&self) -> Vec<Vec<u8>> {
+        let dead_letters = self.dead_letters.read().expect("Dead letters lock is poisoned");
+        dead_letters.iter().cloned().collect()
+    }
+
+    /// Make the next `count` inbound SWIM or gossip messages fail to decode. Only available with
+    /// the `functional` feature, for testing.
+    #[cfg(feature = "functional")]
+    pub fn fail_next_decodes(&self, count: usize) {
+        self.failure_injection.fail_next_decodes(count);
+    }
+
+    /// Returns true, using up one injected failure, if this decode should fail. Without the
+    /// `functional` feature, this is always false.
+    pub fn inject_decode_failure(&self) -> bool {
+        self.failure_injection.take_decode_failure()
+    }
+
+    /// Make the next `count` rumors we push fail to encode. Only available with the `functional`
+    /// feature, for testing.
+    #[cfg(feature = "functional")]
+    pub fn fail_next_encodes(&self, count: usize) {
+        self.failure_injection.fail_next_encodes(count);
+    }
+
+    /// Returns true, using up one injected failure, if this encode should fail. Without the
+    /// `functional` feature, this is always false.
+    pub fn inject_encode_failure(&self) -> bool {
+        self.failure_injection.take_encode_failure()
+    }
+
     /// Stop the outbound and inbound threads from processing work.
     pub fn pause(&mut self) {
         self.pause.compare_and_swap(false, true, Ordering::Relaxed);
@@ -279,6 +527,30 @@ impl Server {
         &self.name
     }
 
+    /// Return a digest of this servers view of the membership. Two servers with the same digest
+    /// agree on the id, incarnation and health of every member, including themselves.
+    pub fn member_digest(&self) -> u64 {
+        let mut entries: Vec<(String, u64, Health)> = Vec::new();
+        {
+            let me = self.member.read().expect("Member lock is poisoned");
+            entries.push((String::from(me.get_id()), me.get_incarnation(), Health::Alive));
+        }
+        // This will lead to nested read locks if you don't deal with making a copy
+        let mut members: Vec<Member> = Vec::new();
+        self.member_list.with_member_list(|ml| {
+            members = ml.values().map(|v| v.clone()).collect();
+        });
+        for member in members.into_iter().filter(|m| m.get_id() != self.member_id()) {
+            if let Some(health) = self.member_list.health_of(&member) {
+                entries.push((String::from(member.get_id()), member.get_incarnation(), health));
+            }
+        }
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut hasher = DefaultHasher::new();
+        entries.hash(&mut hasher);
+        hasher.finish()
+    }
+
     /// Insert a member to the `MemberList`, and update its `RumorKey` appropriately.
     pub fn insert_member(&self, member: Member, health: Health) {
         let rk: RumorKey = RumorKey::from(&member);
@@ -288,7 +560,9 @@ impl Server {
         let trace_member_id = String::from(member.get_id());
         let trace_incarnation = member.get_incarnation();
         let trace_health = health.clone();
+        let previous_health = self.member_list.health_of(&member);
         if self.member_list.insert(member, health) {
+            self.notify_health_change(&trace_member_id, previous_health, trace_health);
             trace_it!(MEMBERSHIP: self,
                       TraceKind::MemberUpdate,
                       trace_member_id,
@@ -307,7 +581,9 @@ impl Server {
         let trace_member_id = String::from(member.get_id());
         let trace_incarnation = member.get_incarnation();
         let trace_health = health.clone();
+        let previous_health = self.member_list.health_of(member);
         if self.member_list.insert_health(member, health) {
+            self.notify_health_change(&trace_member_id, previous_health, trace_health);
             trace_it!(MEMBERSHIP: self,
                       TraceKind::MemberUpdate,
                       trace_member_id,
@@ -337,8 +613,10 @@ impl Server {
         let trace_member_id = String::from(member.get_id());
         let trace_incarnation = member.get_incarnation();
         let trace_health = health.clone();
+        let previous_health = self.member_list.health_of(&member);
 
         if self.member_list.insert(member, health) || incremented_incarnation {
+            self.notify_health_change(&trace_member_id, previous_health, trace_health);
             trace_it!(MEMBERSHIP: self,
                       TraceKind::MemberUpdate,
                       trace_member_id,
@@ -358,32 +636,201 @@ impl Server {
     /// Insert a service rumor into the service store.
     pub fn insert_service(&self, service: Service) {
         let rk = RumorKey::from(&service);
+        let joined = !self.service_store.contains_rumor(service.key(), service.id());
         if self.service_store.insert(service) {
+            if joined {
+                self.group_subscribers.send(&rk.key, GroupEvent::MemberJoined(rk.id.clone()));
+            }
             self.rumor_list.insert(rk);
         }
     }
 
-    /// Get all the Member ID's who are present in a given service group.
-    pub fn get_electorate(&self, key: &str) -> Vec<String> {
+    /// Subscribe to the events for a single service group: members joining or leaving it, and
+    /// changes of leader.
+    pub fn subscribe_group(&self, service_group: ServiceGroup) -> mpsc::Receiver<GroupEvent> {
+        self.group_subscribers.subscribe(format!("{}", service_group))
+    }
+
+    /// Tell the subscribers of every service group a member runs in when it has left, or come
+    /// back from the dead. A member that has left also loses its rumor digest; if it comes back,
+    /// it sends us a fresh one.
+    pub fn notify_health_change(&self,
+                                member_id: &str,
+                                previous_health: Option<Health>,
+                                health: Health) {
+        if health == Health::Confirmed {
+            self.peer_rumor_digests.remove(member_id);
+        }
+        let event = match (previous_health, health) {
+            (Some(Health::Confirmed), Health::Alive) => {
+                GroupEvent::MemberJoined(String::from(member_id))
+            }
+            (Some(Health::Alive), Health::Confirmed) |
+            (Some(Health::Suspect), Health::Confirmed) => {
+                GroupEvent::MemberLeft(String::from(member_id))
+            }
+            _ => return,
+        };
+        let mut groups = vec![];
+        self.service_store.with_keys(|(service_group, services)| {
+            if services.contains_key(member_id) {
+                groups.push(service_group.clone());
+            }
+        });
+        for service_group in groups.iter() {
+            self.group_subscribers.send(service_group, event.clone());
+        }
+    }
+
+    /// The electorate settle window: how long, in milliseconds, a change in a service groups
+    /// census must hold on this member before its elections count it. Zero, the default, counts
+    /// every change immediately.
+    pub fn electorate_settle_window_ms(&self) -> usize {
+        self.electorate_settle_window_ms.load(Ordering::Relaxed)
+    }
+
+    /// Set the electorate settle window. It keeps this member from restarting or denying quorum to
+    /// an election over a brief flicker in who it thinks is alive. It does not make members agree
+    /// on an electorate; each member settles its own view.
+    pub fn set_electorate_settle_window_ms(&self, window_ms: usize) {
+        self.electorate_settle_window_ms.store(window_ms, Ordering::Relaxed);
+    }
+
+    /// Serialize every member, service and election rumor we know of into a single snapshot,
+    /// suitable for `import_rumors`. Each rumor is written as a length-delimited protobuf
+    /// `Rumor`.
+    ///
+    /// # Errors
+    ///
+    /// * Returns `Error::ProtobufError` if any rumor cannot be serialized; we never hand back a
+    ///   partial snapshot
+    pub fn export_rumors(&self) -> Result<Vec<u8>> {
+        let mut rumors: Vec<ProtoRumor> = Vec::new();
+        // This will lead to nested read locks if you don't deal with making a copy
+        let mut member_ids: Vec<String> = Vec::new();
+        self.member_list.with_member_list(|ml| { member_ids = ml.keys().map(|k| k.clone()).collect(); });
+        for member_id in member_ids.iter() {
+            let mut rumor = ProtoRumor::new();
+            rumor.set_field_type(Rumor_Type::Member);
+            rumor.set_member(self.member_list.membership_for(member_id));
+            rumor.set_from_id(String::from(self.member_id()));
+            rumors.push(rumor);
+        }
+        self.service_store.with_keys(|(_, services)| {
+            for service in services.values() {
+                rumors.push(service.proto.clone());
+            }
+        });
+        self.election_store.with_keys(|(_, elections)| {
+            for election in elections.values() {
+                rumors.push(election.proto.clone());
+            }
+        });
+        let mut bytes = Vec::new();
+        for rumor in rumors.iter() {
+            try!(rumor.write_length_delimited_to_vec(&mut bytes));
+        }
+        Ok(bytes)
+    }
+
+    /// Restore rumors from a snapshot created by `export_rumors`. Rumors are merged into the
+    /// member list and stores exactly as if they had been received over gossip, without starting
+    /// new elections. The whole snapshot is parsed before anything is inserted, so a malformed
+    /// snapshot changes nothing.
+    ///
+    /// # Errors
+    ///
+    /// * Returns `Error::ProtobufError` if the snapshot cannot be parsed
+    pub fn import_rumors(&self, bytes: &[u8]) -> Result<()> {
+        let mut rumors: Vec<ProtoRumor> = Vec::new();
+        {
+            let mut is = CodedInputStream::from_bytes(bytes);
+            while !try!(is.eof()) {
+                rumors.push(try!(is.read_message()));
+            }
+        }
+        for mut rumor in rumors.into_iter() {
+            match rumor.get_field_type() {
+                Rumor_Type::Member => {
+                    let member = rumor.mut_member().take_member().into();
+                    let health = rumor.mut_member().get_health().into();
+                    self.insert_member_from_rumor(member, health);
+                }
+                Rumor_Type::Service => self.insert_service(rumor.into()),
+                Rumor_Type::Election => {
+                    let election: Election = rumor.into();
+                    let rk = RumorKey::from(&election);
+                    if self.election_store.insert(election) {
+                        self.update_leader(&rk.key);
+                        self.rumor_list.insert(rk);
+                    }
+                }
+                k => warn!("Not importing rumor of unexpected type {:?}", k),
+            }
+        }
+        Ok(())
+    }
+
+    /// The census of a service group as this member sees it right now, before settling.
+    fn live_census(&self, key: &str) -> Census {
+        let mut population = vec![];
         let mut electorate = vec![];
         self.service_store.with_rumors(key, |s| {
+            population.push(String::from(s.get_member_id()));
             if self.member_list
                 .check_health_of_by_id(s.get_member_id(), Health::Alive) {
                 electorate.push(String::from(s.get_member_id()));
             }
         });
-        electorate
+        Census::new(population, electorate)
+    }
+
+    /// Record the current census of a service group, and return the settled census; see
+    /// `set_electorate_settle_window_ms`. The write lock is only taken when the observation changes the
+    /// snapshot.
+    pub fn observe_census(&self, key: &str) -> Census {
+        let census = self.live_census(key);
+        if census.population.is_empty() {
+            self.electorates.write().expect("Electorates lock is poisoned").remove(key);
+            return census;
+        }
+        let settle = TimeDuration::milliseconds(self.electorate_settle_window_ms() as i64);
+        {
+            let electorates = self.electorates.read().expect("Electorates lock is poisoned");
+            if let Some(settled) = electorates.get(key).and_then(|es| es.peek(&census, settle)) {
+                return settled;
+            }
+        }
+        let mut electorates = self.electorates.write().expect("Electorates lock is poisoned");
+        electorates.entry(String::from(key))
+            .or_insert_with(|| ElectorateSnapshot::new(census.clone()))
+            .observe(census, settle)
+    }
+
+    /// Get all the Member ID's who are present in a given service group.
+    ///
+    /// This is the last settled electorate, if we have observed the group with `observe_census`,
+    /// and the live electorate otherwise. Looking it up changes nothing.
+    pub fn get_electorate(&self, key: &str) -> Vec<String> {
+        let settled = {
+            let electorates = self.electorates.read().expect("Electorates lock is poisoned");
+            electorates.get(key).map(|es| es.census().electorate.clone())
+        };
+        settled.unwrap_or_else(|| self.live_census(key).electorate)
     }
 
     /// Check if a given service group has quorum to run an election.
     ///
     /// A given group has quorum if, from this servers perspective, it has an alive population that
-    /// is over 50%, and at least 3 members.
+    /// is over 50%, and at least 3 members. Checking records an observation of the census; see
+    /// `observe_census`.
     pub fn check_quorum(&self, key: &str) -> bool {
-        let electorate = self.get_electorate(key);
+        self.census_has_quorum(&self.observe_census(key))
+    }
 
-        let total_population = self.service_store.len_for_key(key);
-        let alive_population = electorate.len();
+    fn census_has_quorum(&self, census: &Census) -> bool {
+        let total_population = census.population.len();
+        let alive_population = census.electorate.len();
 
         if total_population < 3 {
             info!("Quorum size: {}/3 - election cannot complete",
@@ -400,6 +847,24 @@ impl Server {
         alive_population >= ((total_population / 2) + 1)
     }
 
+    /// Drop the electorate snapshot of every service group we are no longer electing a leader
+    /// for.
+    fn prune_electorates(&self) {
+        let mut keys = vec![];
+        self.election_store.with_keys(|(service_group, rumors)| {
+            if !rumors.is_empty() &&
+               self.service_store.contains_rumor(service_group, self.member_id()) {
+                keys.push(service_group.clone());
+            }
+        });
+        let mut electorates = self.electorates.write().expect("Electorates lock is poisoned");
+        let stale: Vec<String> =
+            electorates.keys().filter(|key| !keys.contains(key)).map(|key| key.clone()).collect();
+        for key in stale.iter() {
+            electorates.remove(key);
+        }
+    }
+
     /// Start an election for the given service group, declaring this members suitability and the
     /// term for the election.
     pub fn start_election(&self, sg: ServiceGroup, suitability: u64, term: u64) {
@@ -411,31 +876,78 @@ impl Server {
         }
         self.election_store
             .insert(e);
+        self.update_leader(&ek.key);
         self.rumor_list.insert(ek);
     }
 
+    /// Register a callback to run whenever the leader of the given service group changes. It is
+    /// called with the member id of the new leader, or `None` when the group is leaderless.
+    ///
+    /// Callbacks see changes in the order they were applied. They run on the thread that applied
+    /// the election rumor, or on one already delivering an earlier change, so they should return
+    /// quickly.
+    pub fn on_leader_change(&self, service_group: ServiceGroup, callback: LeaderChangeCallback) {
+        self.leader_change_callbacks.insert(format!("{}", service_group), callback);
+    }
+
+    /// Check the election for the given service group, and fire the leader change callbacks if
+    /// the winner of the finished election is not the one we saw last.
+    fn update_leader(&self, key: &str) {
+        let mut leader = None;
+        self.election_store.with_rumor(key, "election", |e| {
+            if let Some(election) = e {
+                if election.is_finished() {
+                    leader = Some(String::from(election.get_member_id()));
+                }
+            }
+        });
+        if self.leader_change_callbacks.update(key, leader.clone()) {
+            self.group_subscribers.send(key, GroupEvent::LeaderChanged(leader));
+        }
+    }
+
     /// Check to see if this server needs to restart a given election. This happens when:
     ///
     /// a) We are the leader, and we have lost quorum with the rest of the group.
     /// b) We are not the leader, and we have detected that the leader is confirmed dead.
     pub fn restart_elections(&self) {
+        self.prune_electorates();
+        // Observe each group once up front, so we never take the electorates lock while holding
+        // the election store.
+        let mut service_groups = vec![];
+        self.election_store.with_keys(|(service_group, _)| {
+            if self.service_store.contains_rumor(&service_group, self.member_id()) {
+                service_groups.push(service_group.clone());
+            }
+        });
+        let censuses: HashMap<String, Census> = service_groups.into_iter()
+            .map(|sg| {
+                let census = self.observe_census(&sg);
+                (sg, census)
+            })
+            .collect();
         let mut elections_to_restart = vec![];
         self.election_store.with_keys(|(service_group, rumors)| {
-            if self.service_store.contains_rumor(&service_group, self.member_id()) {
+            if let Some(census) = censuses.get(service_group) {
                 // This is safe; there is only one id for an election, and it is "election"
                 let election = rumors.get("election")
                     .expect("Lost an election struct between looking it up and reading it.");
                 // If we are finished, and the leader is dead, we should restart the election
                 if election.get_member_id() == self.member_id() {
                     // If we are the leader, and we have lost quorum, we should restart the election
-                    if self.check_quorum(election.key()) == false {
+                    if self.census_has_quorum(census) == false {
                         warn!("Restarting election with a new term as the leader has lost quorum: {:?}", election);
                         elections_to_restart.push((String::from(&service_group[..]), election.get_term()));
 
                     }
                 } else if election.get_status() == Election_Status::Finished {
+                    // While the electorate is settling, a leader some of us think is dead is
+                    // still counted as the leader.
                     if self.member_list
-                        .check_health_of_by_id(election.get_member_id(), Health::Confirmed) {
+                        .check_health_of_by_id(election.get_member_id(), Health::Confirmed) &&
+                       !census.electorate
+                        .iter()
+                        .any(|id| id == election.get_member_id()) {
                             warn!("Restarting election with a new term as the leader is dead {}: {:?}", self.member_id(), election);
                             elections_to_restart.push((String::from(&service_group[..]), election.get_term()));
                     }
@@ -487,8 +999,9 @@ impl Server {
                 // If we are the member that this election is voting for, then check to see if the election
                 // is over! If it is, mark this election as final before you process it.
                 if self.member_id() == election.get_member_id() {
-                    if self.check_quorum(election.key()) {
-                        let electorate = self.get_electorate(election.key());
+                    let census = self.observe_census(election.key());
+                    if self.census_has_quorum(&census) {
+                        let electorate = census.electorate;
                         let mut num_votes = 0;
                         for vote in election.get_votes().iter() {
                             if electorate.contains(vote) {
@@ -530,6 +1043,7 @@ impl Server {
             }
         }
         if self.election_store.insert(election) {
+            self.update_leader(&rk.key);
             self.rumor_list.insert(rk);
         }
     }
@@ -545,12 +1059,79 @@ impl fmt::Display for Server {
     }
 }
 
+/// Failures injected into message handling, so tests can exercise the paths for messages we
+/// cannot decode or encode.
+#[cfg(feature = "functional")]
+#[derive(Debug, Clone, Default)]
+pub struct FailureInjection {
+    fail_next_decodes: Arc<AtomicUsize>,
+    fail_next_encodes: Arc<AtomicUsize>,
+}
+
+#[cfg(feature = "functional")]
+impl FailureInjection {
+    /// Make the next `count` decodes fail.
+    pub fn fail_next_decodes(&self, count: usize) {
+        self.fail_next_decodes.store(count, Ordering::SeqCst);
+    }
+
+    /// Make the next `count` encodes fail.
+    pub fn fail_next_encodes(&self, count: usize) {
+        self.fail_next_encodes.store(count, Ordering::SeqCst);
+    }
+
+    /// Returns true, using up one injected failure, if this decode should fail.
+    pub fn take_decode_failure(&self) -> bool {
+        take_injected_failure(&self.fail_next_decodes)
+    }
+
+    /// Returns true, using up one injected failure, if this encode should fail.
+    pub fn take_encode_failure(&self) -> bool {
+        take_injected_failure(&self.fail_next_encodes)
+    }
+}
+
+/// Decrements the count of injected failures, returning true if there was one left to take.
+#[cfg(feature = "functional")]
+fn take_injected_failure(remaining: &AtomicUsize) -> bool {
+    let mut current = remaining.load(Ordering::SeqCst);
+    while current > 0 {
+        let previous = remaining.compare_and_swap(current, current - 1, Ordering::SeqCst);
+        if previous == current {
+            return true;
+        }
+        current = previous;
+    }
+    false
+}
+
+/// Without the `functional` feature, no failures are ever injected.
+#[cfg(not(feature = "functional"))]
+#[derive(Debug, Clone, Default)]
+pub struct FailureInjection;
+
+#[cfg(not(feature = "functional"))]
+impl FailureInjection {
+    /// Always false; there is nothing to inject.
+    pub fn take_decode_failure(&self) -> bool {
+        false
+    }
+
+    /// Always false; there is nothing to inject.
+    pub fn take_encode_failure(&self) -> bool {
+        false
+    }
+}
+
 #[cfg(test)]
 mod tests {
     mod server {
+        use habitat_core::service::ServiceGroup;
+
         use server::Server;
         use server::timing::Timing;
-        use member::Member;
+        use member::{Health, Member};
+        use service::Service;
         use trace::Trace;
         use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
 
@@ -585,5 +1166,91 @@ mod tests {
             let server = start_server();
             server.start(Timing::default()).expect("Server failed to start");
         }
+
+        #[test]
+        #[cfg(feature = "functional")]
+        fn injected_decode_failures_are_used_up() {
+            let server = start_server();
+            server.fail_next_decodes(2);
+            assert!(server.inject_decode_failure());
+            assert!(server.inject_decode_failure());
+            assert_eq!(server.inject_decode_failure(), false);
+        }
+
+        #[test]
+        #[cfg(feature = "functional")]
+        fn injected_encode_failures_are_used_up() {
+            let server = start_server();
+            server.fail_next_encodes(1);
+            assert_eq!(server.inject_decode_failure(), false);
+            assert!(server.inject_encode_failure());
+            assert_eq!(server.inject_encode_failure(), false);
+        }
+
+        #[test]
+        fn export_and_import_rumors_round_trips_the_rumor_stores() {
+            let server = start_server();
+            for name in vec!["witcher", "geralt"] {
+                let mut service = Service::new(server.member_id(),
+                                               ServiceGroup::new(name, "prod", None),
+                                               "localhost",
+                                               "127.0.0.1",
+                                               vec![4040]);
+                service.set_incarnation(3);
+                server.insert_service(service);
+            }
+            server.insert_service(Service::new("someone-else",
+                                               ServiceGroup::new("witcher", "prod", None),
+                                               "localhost",
+                                               "127.0.0.2",
+                                               vec![4040]));
+            server.start_election(ServiceGroup::new("witcher", "prod", None), 0, 2);
+            let suspect = Member::new();
+            server.insert_member(suspect.clone(), Health::Suspect);
+
+            let restored = start_server();
+            let bytes = server.export_rumors().expect("Cannot export rumors");
+            restored.import_rumors(&bytes).expect("Cannot import rumors");
+
+            let mut count = 0;
+            server.service_store.with_keys(|(key, services)| {
+                for (id, service) in services.iter() {
+                    count += 1;
+                    restored.service_store
+                        .with_rumor(key, id, |r| assert_eq!(r, Some(service)));
+                }
+            });
+            server.election_store.with_keys(|(key, elections)| {
+                for (id, election) in elections.iter() {
+                    count += 1;
+                    restored.election_store
+                        .with_rumor(key, id, |r| assert_eq!(r, Some(election)));
+                }
+            });
+            assert_eq!(count, 4);
+            assert_eq!(restored.member_list.health_of(&suspect), Some(Health::Suspect));
+            assert_eq!(restored.service_store.len_for_key("witcher.prod"), 2);
+            assert_eq!(restored.service_store.len_for_key("geralt.prod"), 1);
+            assert_eq!(restored.election_store.len_for_key("witcher.prod"), 1);
+        }
+
+        #[test]
+        fn import_rumors_inserts_nothing_from_a_truncated_snapshot() {
+            let server = start_server();
+            server.insert_service(Service::new(server.member_id(),
+                                               ServiceGroup::new("witcher", "prod", None),
+                                               "localhost",
+                                               "127.0.0.1",
+                                               vec![4040]));
+            server.insert_member(Member::new(), Health::Alive);
+            let mut bytes = server.export_rumors().expect("Cannot export rumors");
+            let truncated = bytes.len() - 1;
+            bytes.truncate(truncated);
+
+            let restored = start_server();
+            assert!(restored.import_rumors(&bytes).is_err());
+            assert_eq!(restored.service_store.len_for_key("witcher.prod"), 0);
+            assert_eq!(restored.member_list.len(), 0);
+        }
     }
 }
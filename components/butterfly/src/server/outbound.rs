@@ -16,6 +16,8 @@
 //!
 //! This module handles the implementation of the swim probe protocol.
 
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
 use std::sync::atomic::Ordering;
 use std::sync::mpsc;
 use std::net::{SocketAddr, UdpSocket};
@@ -51,6 +53,13 @@ impl fmt::Display for AckFrom {
     }
 }
 
+/// A probe that is waiting on an Ack.
+struct Probe {
+    member: Member,
+    ack_from: AckFrom,
+    timeout: SteadyTime,
+}
+
 /// The outbound thread
 pub struct Outbound<'a> {
     pub server: &'a Server,
@@ -74,11 +83,11 @@ impl<'a> Outbound<'a> {
         }
     }
 
-    /// Run the outbound thread. Gets a list of members to pinmg, then walks the list, probing each
-    /// member.
+    /// Run the outbound thread. Gets a list of members to ping, then walks the list, probing
+    /// `Timing::probes_per_period` members each protocol period.
     ///
-    /// If the probe completes before the next protocol period is scheduled, waits for the protocol
-    /// period to finish before starting the next probe.
+    /// If the probes complete before the next protocol period is scheduled, waits for the
+    /// protocol period to finish before starting the next probes.
     pub fn run(&mut self) {
         self.server.member_list.with_initial_members(|member| {
             ping(&self.server,
@@ -88,6 +97,8 @@ impl<'a> Outbound<'a> {
                  None);
         });
 
+        let probes_per_period = cmp::max(self.timing.probes_per_period, 1);
+
         loop {
             if self.server.pause.load(Ordering::Relaxed) {
                 thread::sleep(Duration::from_millis(100));
@@ -104,21 +115,21 @@ impl<'a> Outbound<'a> {
                     .expect("Member is poisoned")
                     .get_id());
 
-            for member in check_list {
-                if self.server.member_list.pingable(&member) {
-                    // This is the timeout for the next protocol period - if we
-                    // complete faster than this, we want to wait in the end
-                    // until this timer expires.
-                    let next_protocol_period = self.timing.next_protocol_period();
+            for period in check_list.chunks(probes_per_period) {
+                // This is the timeout for the next protocol period - if we
+                // complete faster than this, we want to wait in the end
+                // until this timer expires.
+                let next_protocol_period = self.timing.next_protocol_period();
 
-                    self.probe(member);
+                if self.probe(period.to_vec()) == 0 {
+                    continue;
+                }
 
-                    if SteadyTime::now() <= next_protocol_period {
-                        let wait_time = next_protocol_period - SteadyTime::now();
-                        debug!("Waiting {} until the next protocol period",
-                               wait_time.num_milliseconds());
-                        thread::sleep(Duration::from_millis(wait_time.num_milliseconds() as u64));
-                    }
+                if SteadyTime::now() <= next_protocol_period {
+                    let wait_time = next_protocol_period - SteadyTime::now();
+                    debug!("Waiting {} until the next protocol period",
+                           wait_time.num_milliseconds());
+                    thread::sleep(Duration::from_millis(wait_time.num_milliseconds() as u64));
                 }
             }
         }
@@ -127,84 +138,82 @@ impl<'a> Outbound<'a> {
     ///
     /// Probe Loop
     ///
-    /// First, we send the ping to the remote address. This operation never blocks - we just
-    /// pass the data straight on to the kernel for UDP goodness. Then we grab a timer for how
-    /// long we're willing to run this phase, and start listening for Ack packets from the
-    /// Inbound thread. If we receive an Ack that is for any Member other than the one we are
-    /// currently pinging, we discard it. Otherwise, we set the address for the Member whose Ack
-    /// we received to the one we saw on the wire, and insert it into the MemberList.
+    /// Probes every member in the list, keeping at most `Timing::max_probes_in_flight` probes
+    /// outstanding. The rest of the members wait in a queue, and each one starts as soon as an
+    /// earlier probe frees its slot. We check that a member is still pingable right before we
+    /// start its probe.
+    ///
+    /// Starting a probe sends the ping to the remote address. This operation never blocks - we
+    /// just pass the data straight on to the kernel for UDP goodness. Then we listen for Ack
+    /// packets from the Inbound thread. If we receive an Ack that is for any Member other than
+    /// the ones we are currently probing, we discard it. Otherwise, we set the address for the
+    /// Member whose Ack we received to the one we saw on the wire, insert it into the
+    /// MemberList, and free its slot.
     ///
-    /// If we don't receive anything on the channel, we check if the current time has exceeded
-    /// our timeout. If it has, we break out of the Ping loop, and proceed to the PingReq loop.
-    /// If the timer has not been exceeded, we park this thread for
+    /// If we don't receive anything on the channel, we check whether any probe has exceeded its
+    /// timeout. A probe that times out waiting on its Ping moves on to PingReq; one that times out
+    /// waiting on its PingReq marks the member as Suspect. Then we park this thread for
     /// PING_RECV_QUEUE_EMPTY_SLEEP_MS, and try again.
     ///
-    /// If we don't recieve anything at all in the Ping/PingReq loop, we mark the member as Suspect.
-    fn probe(&mut self, member: Member) {
-        let addr = member.swim_socket_address();
-
-        trace_it!(PROBE: &self.server, TraceKind::ProbeBegin, member.get_id(), addr);
-
-        // Ping the member, and wait for the ack.
-        ping(self.server, &self.socket, &member, addr, None);
-        if self.recv_ack(&member, addr, AckFrom::Ping) {
-            trace_it!(PROBE: &self.server, TraceKind::ProbeAckReceived, member.get_id(), addr);
-            trace_it!(PROBE: &self.server, TraceKind::ProbeComplete, member.get_id(), addr);
-            return;
-        }
-
-        self.server.member_list.with_pingreq_targets(self.server.member_id(), member.get_id(), |pingreq_target| {
-            trace_it!(PROBE: &self.server, TraceKind::ProbePingReq, pingreq_target.get_id(), pingreq_target.get_address());
-            pingreq(self.server, &self.socket, &pingreq_target, &member);
-        });
-        if !self.recv_ack(&member, addr, AckFrom::PingReq) {
-            // We mark as suspect when we fail to get a response from the PingReq. That moves us
-            // into the suspicion phase, where anyone marked as suspect has a certain number of
-            // protocol periods to recover.
-            warn!("Marking {} as Suspect", member.get_id());
-            trace_it!(PROBE: &self.server, TraceKind::ProbeSuspect, member.get_id(), addr);
-            trace_it!(PROBE: &self.server, TraceKind::ProbeComplete, member.get_id(), addr);
-            self.server.insert_member(member, Health::Suspect);
-        } else {
-            trace_it!(PROBE: &self.server, TraceKind::ProbeComplete, member.get_id(), addr);
-        }
-    }
+    /// Returns the number of members we actually probed.
+    fn probe(&mut self, members: Vec<Member>) -> usize {
+        let max_probes_in_flight = cmp::max(self.timing.max_probes_in_flight, 1);
+        let mut queue: VecDeque<Member> = members.into_iter().collect();
+        let mut outstanding: HashMap<String, Probe> = HashMap::new();
+        let mut probed = 0;
 
-    /// Listen for an ack from the `Inbound` thread.
-    fn recv_ack(&mut self, member: &Member, addr: SocketAddr, ack_from: AckFrom) -> bool {
-        let timeout = match ack_from {
-            AckFrom::Ping => self.timing.ping_timeout(),
-            AckFrom::PingReq => self.timing.pingreq_timeout(),
-        };
         loop {
+            while outstanding.len() < max_probes_in_flight {
+                let member = match queue.pop_front() {
+                    Some(member) => member,
+                    None => break,
+                };
+                if !self.server.member_list.pingable(&member) {
+                    continue;
+                }
+                let addr = member.swim_socket_address();
+                trace_it!(PROBE: &self.server, TraceKind::ProbeBegin, member.get_id(), addr);
+                ping(self.server, &self.socket, &member, addr, None);
+                self.server.probe_started();
+                probed += 1;
+                outstanding.insert(String::from(member.get_id()),
+                                   Probe {
+                                       member: member,
+                                       ack_from: AckFrom::Ping,
+                                       timeout: self.timing.ping_timeout(),
+                                   });
+            }
+            if outstanding.is_empty() {
+                return probed;
+            }
+
             match self.rx_inbound.try_recv() {
                 Ok((real_addr, mut swim)) => {
                     let mut ack_from = swim.mut_ack().take_from();
-                    if member.get_id() != ack_from.get_id() {
-                        error!("Discarding ack from {}@{}; expected {}",
-                               ack_from.get_id(),
-                               real_addr,
-                               member.get_id());
-                        // Keep listening, we want the ack we expected
-                        continue;
-                    }
+                    let probe = match outstanding.remove(ack_from.get_id()) {
+                        Some(probe) => probe,
+                        None => {
+                            error!("Discarding ack from {}@{}; not probing it",
+                                   ack_from.get_id(),
+                                   real_addr);
+                            // Keep listening, we want the acks we expected
+                            continue;
+                        }
+                    };
                     // If this was forwarded to us, we want to retain the address of the member who
                     // sent the ack, not the one we recieved on the socket.
                     if !swim.get_ack().has_forward_to() {
                         ack_from.set_address(format!("{}", real_addr.ip()));
                     }
+                    let addr = probe.member.swim_socket_address();
+                    trace_it!(PROBE: &self.server, TraceKind::ProbeAckReceived, probe.member.get_id(), addr);
+                    trace_it!(PROBE: &self.server, TraceKind::ProbeComplete, probe.member.get_id(), addr);
+                    self.server.probe_finished();
                     let ack_from_member: Member = ack_from.into();
                     self.server.insert_member(ack_from_member, Health::Alive);
-                    // We got the ack we are looking for; return.
-                    return true;
                 }
                 Err(mpsc::TryRecvError::Empty) => {
-                    if SteadyTime::now() > timeout {
-                        warn!("Timed out waiting for Ack from {}@{}",
-                              member.get_id(),
-                              addr);
-                        return false;
-                    }
+                    self.expire_probes(&mut outstanding);
                     thread::sleep(Duration::from_millis(PING_RECV_QUEUE_EMPTY_SLEEP_MS));
                 }
                 Err(mpsc::TryRecvError::Disconnected) => {
@@ -213,6 +222,45 @@ impl<'a> Outbound<'a> {
             }
         }
     }
+
+    /// Moves every probe that has timed out on to its next phase: a Ping that timed out sends
+    /// PingReqs, and a PingReq that timed out marks the member as Suspect and frees its slot.
+    fn expire_probes(&mut self, outstanding: &mut HashMap<String, Probe>) {
+        let now = SteadyTime::now();
+        let expired: Vec<String> = outstanding.iter()
+            .filter(|&(_, probe)| now > probe.timeout)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in expired.into_iter() {
+            let mut probe = outstanding.remove(&id).expect("Expired probe vanished");
+            let addr = probe.member.swim_socket_address();
+            warn!("Timed out waiting for {} Ack from {}@{}",
+                  probe.ack_from,
+                  probe.member.get_id(),
+                  addr);
+            match probe.ack_from {
+                AckFrom::Ping => {
+                    self.server.member_list.with_pingreq_targets(self.server.member_id(), probe.member.get_id(), |pingreq_target| {
+                        trace_it!(PROBE: &self.server, TraceKind::ProbePingReq, pingreq_target.get_id(), pingreq_target.get_address());
+                        pingreq(self.server, &self.socket, &pingreq_target, &probe.member);
+                    });
+                    probe.ack_from = AckFrom::PingReq;
+                    probe.timeout = self.timing.pingreq_timeout();
+                    outstanding.insert(id, probe);
+                }
+                AckFrom::PingReq => {
+                    // We mark as suspect when we fail to get a response from the PingReq. That
+                    // moves us into the suspicion phase, where anyone marked as suspect has a
+                    // certain number of protocol periods to recover.
+                    warn!("Marking {} as Suspect", probe.member.get_id());
+                    trace_it!(PROBE: &self.server, TraceKind::ProbeSuspect, probe.member.get_id(), addr);
+                    trace_it!(PROBE: &self.server, TraceKind::ProbeComplete, probe.member.get_id(), addr);
+                    self.server.probe_finished();
+                    self.server.insert_member(probe.member, Health::Suspect);
+                }
+            }
+        }
+    }
 }
 
 /// Populate a SWIM message with rumors.
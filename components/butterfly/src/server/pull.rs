@@ -26,6 +26,7 @@ use zmq;
 
 use server::Server;
 use message::swim::{Rumor, Rumor_Type};
+use rumor;
 use trace::TraceKind;
 
 /// Takes a reference to the server itself
@@ -63,10 +64,16 @@ impl<'a> Pull<'a> {
                     continue 'recv;
                 }
             };
+            if self.server.inject_decode_failure() {
+                error!("Error parsing protobuf: injected failure");
+                self.server.dead_letter(&msg);
+                continue 'recv;
+            }
             let mut proto: Rumor = match protobuf::parse_from_bytes(&msg) {
                 Ok(proto) => proto,
                 Err(e) => {
                     error!("Error parsing protobuf: {:?}", e);
+                    self.server.dead_letter(&msg);
                     continue 'recv;
                 }
             };
@@ -76,6 +83,13 @@ impl<'a> Pull<'a> {
                 continue 'recv;
             }
             trace_it!(GOSSIP: &self.server, TraceKind::RecvRumor, proto.get_from_id(), &proto);
+            // A piggybacked digest tells us which versions its member holds. Strip it either way,
+            // so it is never stored or forwarded with the rumor.
+            if let Some(digest) = rumor::take_digest_tag(&mut proto) {
+                if self.server.gossip_suppression() {
+                    self.server.peer_rumor_digests.update(digest);
+                }
+            }
             match proto.get_field_type() {
                 Rumor_Type::Member => {
                     let member = proto.mut_member().take_member().into();
@@ -28,7 +28,7 @@ use zmq;
 
 use message::swim::{Rumor as ProtoRumor, Rumor_Type as ProtoRumor_Type, Member as ProtoMember,
                     Membership as ProtoMembership};
-use rumor::{RumorKey, RumorVec};
+use rumor::{self, RumorKey, RumorVec};
 use member::Member;
 use server::Server;
 use server::timing::Timing;
@@ -84,6 +84,13 @@ impl<'a> Push<'a> {
                                member.get_id());
                         continue;
                     }
+                    // We leave the heat alone, so the member gets everything it missed once gossip
+                    // resumes.
+                    if self.server.is_gossip_paused_for(member.get_id()) {
+                        debug!("Not sending rumors to {} - gossip to it is paused",
+                               member.get_id());
+                        continue;
+                    }
 
                     // Unlike the SWIM mechanism, we don't actually want to send gossip traffic to
                     // persistent members that are confirmed dead. When the failure detector thread
@@ -160,45 +167,52 @@ impl PushWorker {
                 return;
             }
         }
+        // The first rumor we send carries our digest, so the member can skip what we hold.
+        let mut digest = if self.server.gossip_suppression() {
+            Some(self.server.piggyback_digest(&rumors))
+        } else {
+            None
+        };
         'rumorlist: for &(ref rumor_key, ref _heat) in rumors.iter() {
-            let rumor_as_bytes = match rumor_key.kind {
+            if self.server.gossip_suppression() &&
+               self.server.peer_has_rumor(member.get_id(), rumor_key) {
+                debug!("Not sending rumor {:?} to {:?} - it already has it",
+                       rumor_key,
+                       member);
+                continue 'rumorlist;
+            }
+            let mut send_rumor = match rumor_key.kind {
                 ProtoRumor_Type::Member => {
                     let send_rumor = self.create_member_rumor(&rumor_key);
                     trace_it!(GOSSIP: &self.server, TraceKind::SendRumor, member.get_id(), &send_rumor);
-                    match send_rumor.write_to_bytes() {
-                        Ok(bytes) => bytes,
-                        Err(e) => {
-                            println!("Could not write our own rumor to bytes; abandoning \
-                                            sending rumor: {:?}",
-                                     e);
-                            continue 'rumorlist;
-                        }
-                    }
+                    send_rumor
                 }
                 ProtoRumor_Type::Service => {
                     // trace_it!(GOSSIP: &self.server, TraceKind::SendRumor, member.get_id(), &send_rumor);
-                    match self.server
-                        .service_store
-                        .write_to_bytes(&rumor_key.key, &rumor_key.id) {
-                        Ok(bytes) => bytes,
-                        Err(e) => {
-                            println!("Could not write our own rumor to bytes; abandoning \
-                                            sending rumor: {:?}",
-                                     e);
+                    let mut send_rumor = None;
+                    self.server.service_store.with_rumor(&rumor_key.key, &rumor_key.id, |s| {
+                        send_rumor = s.map(|s| s.proto.clone());
+                    });
+                    match send_rumor {
+                        Some(send_rumor) => send_rumor,
+                        None => {
+                            println!("Could not find our own rumor {:?}; abandoning sending rumor",
+                                     rumor_key);
                             continue 'rumorlist;
                         }
                     }
                 }
                 ProtoRumor_Type::Election => {
                     // trace_it!(GOSSIP: &self.server, TraceKind::SendRumor, member.get_id(), &send_rumor);
-                    match self.server
-                        .election_store
-                        .write_to_bytes(&rumor_key.key, &rumor_key.id) {
-                        Ok(bytes) => bytes,
-                        Err(e) => {
-                            println!("Could not write our own rumor to bytes; abandoning \
-                                            sending rumor: {:?}",
-                                     e);
+                    let mut send_rumor = None;
+                    self.server.election_store.with_rumor(&rumor_key.key, &rumor_key.id, |e| {
+                        send_rumor = e.map(|e| e.proto.clone());
+                    });
+                    match send_rumor {
+                        Some(send_rumor) => send_rumor,
+                        None => {
+                            println!("Could not find our own rumor {:?}; abandoning sending rumor",
+                                     rumor_key);
                             continue 'rumorlist;
                         }
                     }
@@ -208,8 +222,30 @@ impl PushWorker {
                     continue 'rumorlist;
                 }
             };
+            if self.server.inject_encode_failure() {
+                println!("Could not write our own rumor to bytes; abandoning sending rumor: \
+                          injected failure");
+                self.server.unencodable_rumor();
+                continue 'rumorlist;
+            }
+            if let Some(digest) = digest.take() {
+                rumor::set_digest_tag(&mut send_rumor, &digest);
+            }
+            let rumor_as_bytes = match send_rumor.write_to_bytes() {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    println!("Could not write our own rumor to bytes; abandoning sending rumor: \
+                              {:?}",
+                             e);
+                    self.server.unencodable_rumor();
+                    continue 'rumorlist;
+                }
+            };
             match socket.send(&rumor_as_bytes[..], 0) {
-                Ok(()) => debug!("Sent rumor {:?} to {:?}", rumor_key, member),
+                Ok(()) => {
+                    debug!("Sent rumor {:?} to {:?}", rumor_key, member);
+                    self.server.record_rumor_sent(member.get_id(), rumor_key);
+                }
                 Err(e) => println!("Could not send rumor to {:?}; ZMQ said: {:?}", member, e),
             }
         }
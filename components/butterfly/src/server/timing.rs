@@ -22,6 +22,10 @@ const PINGREQ_TIMING_DEFAULT_MS: i64 = 2100;
 const SUSPICION_TIMEOUT_DEFAULT_PROTOCOL_PERIODS: i64 = 3;
 /// How long is the gossip period
 const GOSSIP_PERIOD_DEFAULT_MS: i64 = 1000;
+/// How many members are probed in each protocol period.
+const PROBES_PER_PERIOD_DEFAULT: usize = 1;
+/// How many outbound probes may be outstanding at once; probes beyond this wait for a free slot.
+const MAX_PROBES_IN_FLIGHT_DEFAULT: usize = 8;
 
 /// The timing of the outbound threads.
 #[derive(Debug, Clone)]
@@ -30,6 +34,8 @@ pub struct Timing {
     pub pingreq_ms: i64,
     pub gossip_period_ms: i64,
     pub suspicion_timeout_protocol_periods: i64,
+    pub probes_per_period: usize,
+    pub max_probes_in_flight: usize,
 }
 
 impl Default for Timing {
@@ -39,6 +45,8 @@ impl Default for Timing {
             pingreq_ms: PINGREQ_TIMING_DEFAULT_MS,
             gossip_period_ms: GOSSIP_PERIOD_DEFAULT_MS,
             suspicion_timeout_protocol_periods: SUSPICION_TIMEOUT_DEFAULT_PROTOCOL_PERIODS,
+            probes_per_period: PROBES_PER_PERIOD_DEFAULT,
+            max_probes_in_flight: MAX_PROBES_IN_FLIGHT_DEFAULT,
         }
     }
 }
@@ -55,6 +63,8 @@ impl Timing {
             pingreq_ms: pingreq_ms,
             gossip_period_ms: gossip_period_ms,
             suspicion_timeout_protocol_periods: suspicion_timeout_protocol_periods,
+            probes_per_period: PROBES_PER_PERIOD_DEFAULT,
+            max_probes_in_flight: MAX_PROBES_IN_FLIGHT_DEFAULT,
         }
     }
 
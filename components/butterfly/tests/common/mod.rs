@@ -25,6 +25,10 @@ use habitat_butterfly::server::timing::Timing;
 static SERVER_PORT: AtomicUsize = ATOMIC_USIZE_INIT;
 
 pub fn start_server(name: &str) -> Server {
+    start_server_with_timing(name, Timing::default())
+}
+
+pub fn start_server_with_timing(name: &str, timing: Timing) -> Server {
     SERVER_PORT.compare_and_swap(0, 6666, Ordering::Relaxed);
     let swim_port = SERVER_PORT.fetch_add(1, Ordering::Relaxed);
     let gossip_port = SERVER_PORT.fetch_add(1, Ordering::Relaxed);
@@ -39,7 +43,7 @@ pub fn start_server(name: &str) -> Server {
                              Trace::default(),
                              Some(String::from(name)))
         .unwrap();
-    server.start(Timing::default()).expect("Cannot start server");
+    server.start(timing).expect("Cannot start server");
     server
 }
 
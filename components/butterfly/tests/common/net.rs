@@ -24,6 +24,7 @@ use habitat_butterfly::member::{Member, Health};
 use habitat_butterfly::server::timing::Timing;
 use habitat_butterfly::service::Service;
 use habitat_butterfly::message::swim::Election_Status;
+use habitat_butterfly::rumor::RumorKey;
 use habitat_core::service::ServiceGroup;
 
 #[derive(Debug)]
@@ -47,9 +48,13 @@ impl DerefMut for SwimNet {
 
 impl SwimNet {
     pub fn new(count: usize) -> SwimNet {
+        SwimNet::new_with_timing(count, Timing::default())
+    }
+
+    pub fn new_with_timing(count: usize, timing: Timing) -> SwimNet {
         let mut members = Vec::with_capacity(count);
         for x in 0..count {
-            members.push(common::start_server(&format!("{}", x)));
+            members.push(common::start_server_with_timing(&format!("{}", x), timing.clone()));
         }
         SwimNet { members: members }
     }
@@ -100,6 +105,24 @@ impl SwimNet {
         from.remove_from_blacklist(to.member_id());
     }
 
+    pub fn pause_gossip(&self, from_entry: usize, to_entry: usize) {
+        let from =
+            self.members.get(from_entry).expect("Asked for a network member who is out of bounds");
+        let to =
+            self.members.get(to_entry).expect("Asked for a network member who is out of bounds");
+        trace_it!(TEST: &self.members[from_entry], format!("Gossip paused {} {}", self.members[to_entry].name(), self.members[to_entry].member_id()));
+        from.set_gossip_paused_for(String::from(to.member_id()));
+    }
+
+    pub fn unpause_gossip(&self, from_entry: usize, to_entry: usize) {
+        let from =
+            self.members.get(from_entry).expect("Asked for a network member who is out of bounds");
+        let to =
+            self.members.get(to_entry).expect("Asked for a network member who is out of bounds");
+        trace_it!(TEST: &self.members[from_entry], format!("Gossip unpaused {} {}", self.members[to_entry].name(), self.members[to_entry].member_id()));
+        from.unset_gossip_paused_for(to.member_id());
+    }
+
     pub fn health_of(&self, from_entry: usize, to_entry: usize) -> Option<Health> {
         let from =
             self.members.get(from_entry).expect("Asked for a network member who is out of bounds");
@@ -342,6 +365,73 @@ impl SwimNet {
         }
     }
 
+    pub fn wait_for_digest_agreement(&self) -> bool {
+        let rounds_in = self.rounds_in(self.max_rounds());
+        loop {
+            let digests: Vec<u64> = self.members.iter().map(|m| m.member_digest()).collect();
+            if digests.windows(2).all(|d| d[0] == d[1]) {
+                trace_it!(TEST_NET: self, "Digest agreement");
+                return true;
+            }
+            if self.check_rounds(&rounds_in) {
+                println!("Failed digest agreement: {:?}", digests);
+                return false;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    pub fn wait_for_peer_digest(&self, entry: usize, peer: usize, rumor_key: &RumorKey) -> bool {
+        let rounds_in = self.gossip_rounds_in(self.max_gossip_rounds());
+        loop {
+            if self.members[entry].peer_has_rumor(self.members[peer].member_id(), rumor_key) {
+                return true;
+            }
+            if self.check_gossip_rounds(&rounds_in) {
+                println!("Failed peer digest check for {} from {}: {:?}",
+                         entry,
+                         peer,
+                         rumor_key);
+                return false;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    #[cfg(feature = "functional")]
+    pub fn wait_for_unencodable_rumors(&self, entry: usize, count: usize) -> bool {
+        let rounds_in = self.gossip_rounds_in(self.max_gossip_rounds());
+        loop {
+            if self.members[entry].unencodable_rumors() >= count {
+                return true;
+            }
+            if self.check_gossip_rounds(&rounds_in) {
+                println!("Failed unencodable rumor check for {}: saw {}",
+                         entry,
+                         self.members[entry].unencodable_rumors());
+                return false;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    #[cfg(feature = "functional")]
+    pub fn wait_for_malformed_messages(&self, entry: usize, count: usize) -> bool {
+        let rounds_in = self.rounds_in(self.max_rounds());
+        loop {
+            if self.members[entry].malformed_messages() >= count {
+                return true;
+            }
+            if self.check_rounds(&rounds_in) {
+                println!("Failed malformed message check for {}: saw {}",
+                         entry,
+                         self.members[entry].malformed_messages());
+                return false;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
     #[allow(dead_code)]
     pub fn wait_protocol_period(&self) {
         let timing = Timing::default();
@@ -23,6 +23,7 @@ mod common;
 mod rumor;
 
 use habitat_butterfly::member::Health;
+use habitat_butterfly::server::timing::Timing;
 
 #[test]
 fn two_members_meshed_confirm_one_member() {
@@ -46,6 +47,26 @@ fn six_members_meshed_confirm_one_member() {
     assert_wait_for_health_of!(net, 0, Health::Confirmed);
 }
 
+#[test]
+fn six_members_meshed_probes_in_flight_never_exceed_the_cap() {
+    let mut timing = Timing::default();
+    timing.probes_per_period = 5;
+    timing.max_probes_in_flight = 2;
+    let mut net = common::net::SwimNet::new_with_timing(6, timing);
+    net.mesh();
+    trace_it!(TEST: &net[0], "Paused");
+    net[0].pause();
+    assert_wait_for_health_of!(net, 0, Health::Confirmed);
+    for server in net.iter() {
+        assert!(server.probes_in_flight_high_water() <= 2,
+                "Member {} had {} probes in flight",
+                server.name(),
+                server.probes_in_flight_high_water());
+    }
+    assert!(net.iter().any(|server| server.probes_in_flight_high_water() == 2),
+            "No member ever had more than one probe in flight");
+}
+
 #[test]
 fn six_members_meshed_partition_one_node_from_another_node_remains_alive() {
     let mut net = common::net::SwimNet::new(6);
@@ -65,6 +86,20 @@ fn six_members_meshed_partition_half_of_nodes_from_each_other_both_sides_confirm
     assert_wait_for_health_of!(net, [0..3, 3..6], Health::Confirmed);
 }
 
+#[test]
+fn six_members_meshed_reach_digest_agreement_and_lose_it_when_partitioned() {
+    let mut net = common::net::SwimNet::new(6);
+    net.mesh();
+    assert!(net.wait_for_digest_agreement());
+    net.partition(0..3, 3..6);
+    assert_wait_for_health_of!(net, [0..3, 3..6], Health::Confirmed);
+    for left in 0..3 {
+        for right in 3..6 {
+            assert!(net[left].member_digest() != net[right].member_digest());
+        }
+    }
+}
+
 #[test]
 fn six_members_unmeshed_become_fully_meshed_via_gossip() {
     let mut net = common::net::SwimNet::new(6);
@@ -121,6 +156,43 @@ fn six_members_unmeshed_partition_and_rejoin_persistent_peers() {
     assert_wait_for_health_of!(net, [0..3, 3..6], Health::Alive);
 }
 
+#[test]
+#[cfg(feature = "functional")]
+fn two_members_dead_letter_injected_decode_failures_and_recover() {
+    let mut net = common::net::SwimNet::new(2);
+    net[0].member.write().expect("Member lock is poisoned").set_persistent(true);
+    net[1].member.write().expect("Member lock is poisoned").set_persistent(true);
+    net.mesh();
+    assert_wait_for_health_of!(net, [0..2, 0..2], Health::Alive);
+    net[1].fail_next_decodes(3);
+    assert!(net.wait_for_malformed_messages(1, 3));
+    assert_eq!(net[1].malformed_messages(), 3);
+    assert_eq!(net[1].dead_letters().len(), 3);
+
+    net.add_service(0, "witcher");
+    assert_wait_for_health_of!(net, [0..2, 0..2], Health::Alive);
+    net.wait_for_gossip_rounds(2);
+    net[1].service_store.with_rumor("witcher.prod", net[0].member_id(), |u| assert!(u.is_some()));
+    assert_eq!(net[1].malformed_messages(), 3);
+}
+
+#[test]
+#[cfg(feature = "functional")]
+fn two_members_count_injected_encode_failures_and_recover() {
+    let mut net = common::net::SwimNet::new(2);
+    net[0].member.write().expect("Member lock is poisoned").set_persistent(true);
+    net[1].member.write().expect("Member lock is poisoned").set_persistent(true);
+    net.mesh();
+    assert_wait_for_health_of!(net, [0..2, 0..2], Health::Alive);
+    net[0].fail_next_encodes(1);
+    net.add_service(0, "witcher");
+    assert!(net.wait_for_unencodable_rumors(0, 1));
+
+    net.wait_for_gossip_rounds(2);
+    net[1].service_store.with_rumor("witcher.prod", net[0].member_id(), |u| assert!(u.is_some()));
+    assert_eq!(net[0].unencodable_rumors(), 1);
+}
+
 #[test]
 #[ignore]
 fn fifty_members_meshed_confirm_one_member() {
@@ -12,8 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::{Arc, Mutex};
+
 use habitat_butterfly::member::Health;
 use habitat_butterfly::message::swim::Election_Status;
+use habitat_core::service::ServiceGroup;
 
 use common;
 
@@ -203,3 +206,173 @@ fn five_members_elect_a_new_leader_when_they_are_quorum_partitioned() {
         assert_eq!(new_leader_id, String::from(e.unwrap().get_member_id()));
     });
 }
+
+#[test]
+fn three_members_with_a_settled_electorate_keep_their_leader_through_a_brief_partition() {
+    let mut net = common::net::SwimNet::new(3);
+    for server in net.iter() {
+        server.member.write().expect("Member lock is poisoned").set_persistent(true);
+    }
+    net.mesh();
+    net.add_service(0, "witcher");
+    net.add_service(1, "witcher");
+    net.add_service(2, "witcher");
+    net.add_election(0, "witcher", 0);
+    assert_wait_for_health_of!(net, [0..3, 0..3], Health::Alive);
+    assert_wait_for_election_status!(net, [0..3], "witcher.prod", Election_Status::Finished);
+    assert_wait_for_equal_election!(net, [0..3, 0..3], "witcher.prod");
+
+    for server in net.iter() {
+        server.set_electorate_settle_window_ms(60000);
+    }
+
+    let mut leader_id = String::from("");
+    net[0].election_store.with_rumor("witcher.prod", "election", |e| {
+        leader_id = String::from(e.unwrap().get_member_id());
+    });
+    let leader = net.iter().position(|s| s.member_id() == &leader_id[..]).unwrap();
+    let others: Vec<usize> = (0..3).filter(|i| *i != leader).collect();
+
+    // The leader and the rest of the group now disagree about who is alive.
+    for other in others.iter() {
+        net.blacklist(leader, *other);
+        net.blacklist(*other, leader);
+    }
+    for other in others.iter() {
+        assert_wait_for_health_of!(net, leader, *other, Health::Confirmed);
+        assert_wait_for_health_of!(net, *other, leader, Health::Confirmed);
+    }
+    for server in net.iter() {
+        server.restart_elections();
+    }
+    for server in net.iter() {
+        server.election_store.with_rumor("witcher.prod", "election", |e| {
+            assert_eq!(e.unwrap().get_status(), Election_Status::Finished);
+            assert_eq!(e.unwrap().get_term(), 0);
+            assert_eq!(e.unwrap().get_member_id(), &leader_id[..]);
+        });
+    }
+
+    // Once views agree again, everyone settles on the same leader.
+    for other in others.iter() {
+        net.unblacklist(leader, *other);
+        net.unblacklist(*other, leader);
+    }
+    assert_wait_for_health_of!(net, [0..3, 0..3], Health::Alive);
+    assert_wait_for_equal_election!(net, [0..3, 0..3], "witcher.prod");
+    net[leader].election_store.with_rumor("witcher.prod", "election", |e| {
+        assert_eq!(e.unwrap().get_member_id(), &leader_id[..]);
+    });
+}
+
+#[test]
+fn three_members_with_a_settled_electorate_elect_one_new_leader_once_the_window_passes() {
+    let mut net = common::net::SwimNet::new(3);
+    for server in net.iter() {
+        server.member.write().expect("Member lock is poisoned").set_persistent(true);
+    }
+    net.mesh();
+    net.add_service(0, "witcher");
+    net.add_service(1, "witcher");
+    net.add_service(2, "witcher");
+    net.add_election(0, "witcher", 0);
+    assert_wait_for_health_of!(net, [0..3, 0..3], Health::Alive);
+    assert_wait_for_election_status!(net, [0..3], "witcher.prod", Election_Status::Finished);
+    assert_wait_for_equal_election!(net, [0..3, 0..3], "witcher.prod");
+
+    for server in net.iter() {
+        server.set_electorate_settle_window_ms(2000);
+    }
+
+    let mut leader_id = String::from("");
+    net[0].election_store.with_rumor("witcher.prod", "election", |e| {
+        leader_id = String::from(e.unwrap().get_member_id());
+    });
+    let leader = net.iter().position(|s| s.member_id() == &leader_id[..]).unwrap();
+    let others: Vec<usize> = (0..3).filter(|i| *i != leader).collect();
+
+    for other in others.iter() {
+        net.blacklist(leader, *other);
+        net.blacklist(*other, leader);
+    }
+    for other in others.iter() {
+        assert_wait_for_health_of!(net, leader, *other, Health::Confirmed);
+        assert_wait_for_health_of!(net, *other, leader, Health::Confirmed);
+    }
+
+    // The first look at the new electorate starts the settle window.
+    for server in net.iter() {
+        server.restart_elections();
+    }
+    for other in others.iter() {
+        net[*other].election_store.with_rumor("witcher.prod", "election", |e| {
+            assert_eq!(e.unwrap().get_term(), 0);
+            assert_eq!(e.unwrap().get_member_id(), &leader_id[..]);
+        });
+    }
+
+    // Once it has held for the window, the majority elects exactly one new leader. Four gossip
+    // rounds take at least three gossip periods, well past the window.
+    net.wait_for_gossip_rounds(4);
+    for server in net.iter() {
+        server.restart_elections();
+    }
+    for other in others.iter() {
+        assert_wait_for_election_status!(net, *other, "witcher.prod", Election_Status::Finished);
+    }
+    assert_wait_for_equal_election!(net, others[0], others[1], "witcher.prod");
+    net[others[0]].election_store.with_rumor("witcher.prod", "election", |e| {
+        assert_eq!(e.unwrap().get_term(), 1);
+        assert!(e.unwrap().get_member_id() != &leader_id[..]);
+    });
+    net[leader].election_store.with_rumor("witcher.prod", "election", |e| {
+        assert_eq!(e.unwrap().get_status(), Election_Status::NoQuorum);
+    });
+}
+
+#[test]
+fn five_members_fire_leader_change_callbacks_when_the_leader_steps_down() {
+    let mut net = common::net::SwimNet::new(5);
+    net.mesh();
+    let mut changes = Vec::new();
+    for x in 0..5 {
+        net.add_service(x, "witcher");
+        let leaders: Arc<Mutex<Vec<Option<String>>>> = Arc::new(Mutex::new(Vec::new()));
+        let callback_leaders = leaders.clone();
+        net[x].on_leader_change(ServiceGroup::new("witcher", "prod", None),
+                                Box::new(move |leader| {
+                                    callback_leaders.lock().unwrap().push(leader);
+                                }));
+        changes.push(leaders);
+    }
+    net.add_election(0, "witcher", 0);
+    assert_wait_for_election_status!(net, [0..5], "witcher.prod", Election_Status::Finished);
+    assert_wait_for_equal_election!(net, [0..5, 0..5], "witcher.prod");
+    net.wait_for_gossip_rounds(1);
+
+    let mut leader_id = String::from("");
+    net[0].election_store.with_rumor("witcher.prod", "election", |e| {
+        leader_id = String::from(e.unwrap().get_member_id());
+    });
+    for leaders in changes.iter() {
+        assert_eq!(leaders.lock().unwrap().last(), Some(&Some(leader_id.clone())));
+    }
+
+    let leader = net.iter().position(|s| s.member_id() == &leader_id[..]).unwrap();
+    let observer = if leader == 0 { 1 } else { 0 };
+    net[leader].pause();
+    assert_wait_for_health_of!(net, leader, Health::Confirmed);
+    net[observer].restart_elections();
+    assert_wait_for_election_status!(net, observer, "witcher.prod", Election_Status::Running);
+    assert_wait_for_election_status!(net, observer, "witcher.prod", Election_Status::Finished);
+    net.wait_for_gossip_rounds(1);
+
+    let mut new_leader_id = String::from("");
+    net[observer].election_store.with_rumor("witcher.prod", "election", |e| {
+        new_leader_id = String::from(e.unwrap().get_member_id());
+    });
+    assert!(new_leader_id != leader_id);
+    let observed = changes[observer].lock().unwrap();
+    assert!(observed.contains(&None));
+    assert_eq!(observed.last(), Some(&Some(new_leader_id)));
+}
@@ -12,6 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use habitat_butterfly::member::Health;
+use habitat_butterfly::message::swim::{Election_Status, Rumor_Type};
+use habitat_butterfly::rumor::RumorKey;
+use habitat_butterfly::server::event::GroupEvent;
+use habitat_core::service::ServiceGroup;
 
 use common;
 
@@ -23,3 +28,70 @@ fn two_members_share_services() {
     net.wait_for_rounds(2);
     net[1].service_store.with_rumor("witcher.prod", net[0].member_id(), |u| assert!(u.is_some()));
 }
+
+#[test]
+fn three_members_with_gossip_suppression_do_not_send_rumors_the_recipient_heard_elsewhere() {
+    let mut net = common::net::SwimNet::new(3);
+    for server in net.iter() {
+        server.set_gossip_suppression(true);
+    }
+    net.mesh();
+    // Member 1 can only hear the rumor from member 2, never from member 0.
+    net.pause_gossip(0, 1);
+    net.add_service(2, "witcher");
+    let rk = RumorKey::new(Rumor_Type::Service, net[2].member_id(), "witcher.prod");
+    assert!(net.wait_for_peer_digest(0, 1, &rk));
+
+    net.unpause_gossip(0, 1);
+    net.wait_for_gossip_rounds(3);
+    net[1].service_store.with_rumor("witcher.prod", net[2].member_id(), |u| assert!(u.is_some()));
+    assert!(net[2].rumor_send_count(net[1].member_id(), &rk) > 0);
+    assert_eq!(net[0].rumor_send_count(net[1].member_id(), &rk), 0);
+}
+
+#[test]
+fn two_members_with_gossip_paused_stay_alive_and_catch_up_on_resume() {
+    let mut net = common::net::SwimNet::new(2);
+    net.mesh();
+    net.pause_gossip(0, 1);
+    net.add_service(0, "witcher");
+    net.wait_for_gossip_rounds(3);
+    net[1].service_store.with_rumor("witcher.prod", net[0].member_id(), |u| assert!(u.is_none()));
+    assert_wait_for_health_of!(net, 1, 0, Health::Alive);
+    net.unpause_gossip(0, 1);
+    net.wait_for_gossip_rounds(2);
+    net[1].service_store.with_rumor("witcher.prod", net[0].member_id(), |u| assert!(u.is_some()));
+}
+
+#[test]
+fn four_members_group_subscription_only_sees_its_own_group() {
+    let mut net = common::net::SwimNet::new(4);
+    net.mesh();
+    let events = net[0].subscribe_group(ServiceGroup::new("witcher", "prod", None));
+    net.add_service(0, "witcher");
+    net.add_service(1, "witcher");
+    net.add_service(2, "witcher");
+    net.add_service(3, "yennefer");
+    net.add_election(0, "witcher", 0);
+    net.add_election(3, "yennefer", 0);
+    assert_wait_for_election_status!(net, [0..3], "witcher.prod", Election_Status::Finished);
+    net[3].pause();
+    assert_wait_for_health_of!(net, 0, 3, Health::Confirmed);
+    net[2].pause();
+    assert_wait_for_health_of!(net, 0, 2, Health::Confirmed);
+
+    let mut received = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        received.push(event);
+    }
+    let id = |x: usize| String::from(net[x].member_id());
+    assert!(received.contains(&GroupEvent::MemberJoined(id(1))));
+    assert!(received.contains(&GroupEvent::MemberJoined(id(2))));
+    assert!(received.contains(&GroupEvent::MemberLeft(id(2))));
+    assert!(received.iter().any(|e| match *e {
+        GroupEvent::LeaderChanged(Some(_)) => true,
+        _ => false,
+    }));
+    assert!(!received.contains(&GroupEvent::MemberJoined(id(3))));
+    assert!(!received.contains(&GroupEvent::MemberLeft(id(3))));
+}